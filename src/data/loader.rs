@@ -0,0 +1,65 @@
+use std::marker::PhantomData;
+
+use bevy::{
+    asset::{io::Reader, Asset, AssetLoader, AsyncReadExt, LoadContext},
+    utils::BoxedFuture,
+};
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DataLoaderError {
+    #[error("could not read data file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse ron data: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("could not parse json data: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/**
+ Loads any deserializable [`Asset`] from a `.ron` file.
+
+ each data type registers its own extension (ex. `skill.ron`) so that the
+ [`bevy::asset::AssetServer`] can pick the right loader from the file name.
+*/
+pub struct RonLoader<A> {
+    extensions: &'static [&'static str],
+    _marker: PhantomData<fn() -> A>,
+}
+
+impl<A> RonLoader<A> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        Self {
+            extensions,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<A> AssetLoader for RonLoader<A>
+where
+    A: Asset + DeserializeOwned,
+{
+    type Asset = A;
+    type Settings = ();
+    type Error = DataLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let asset = ron::de::from_bytes::<A>(&bytes)?;
+            Ok(asset)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
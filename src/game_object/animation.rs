@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::{Asset, AssetApp, AssetId, AssetServer, Assets, Handle},
+    ecs::{
+        bundle::Bundle,
+        component::Component,
+        entity::Entity,
+        event::{Event, EventReader, EventWriter},
+        query::{Has, With},
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    hierarchy::{Children, Parent},
+    log::debug,
+    math::Vec2,
+    prelude::default,
+    reflect::TypePath,
+    sprite::{SpriteSheetBundle, TextureAtlas, TextureAtlasSprite},
+    time::{Time, Timer, TimerMode},
+    transform::components::GlobalTransform,
+    utils::HashSet,
+};
+use serde::Deserialize;
+
+use crate::{
+    data::RonLoader,
+    states::{ActionState, BattleState},
+    AppState,
+};
+
+use super::{
+    battle::{die, Damage, Dead, Died},
+    monster::Monster,
+    skill::{PendingReleases, SkillReleased},
+    Facing,
+};
+
+pub struct SpriteAnimationPlugin;
+
+impl Plugin for SpriteAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<AnimationSet>()
+            .register_asset_loader(RonLoader::<AnimationSet>::new(&["anim.ron"]))
+            .init_resource::<AnimationAtlases>()
+            .add_event::<AnimationFrameEvent>()
+            .add_systems(
+                Update,
+                (
+                    leave_dying_sprites.after(die),
+                    (
+                        attach_atlases,
+                        drive_animation_state,
+                        animate_controllers,
+                        despawn_finished_corpses,
+                        log_frame_events,
+                    )
+                        .chain(),
+                )
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// what a unit is doing, as far as its sprite is concerned.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnimState {
+    Idle,
+    Move,
+    Cast,
+    Attack,
+    Hit,
+    Death,
+}
+
+/**
+ Clips of a sprite sheet by [`AnimState`], loaded from `*.anim.ron` files.
+
+ a state without a clip plays the idle clip, a one shot state without a clip is skipped.
+*/
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct AnimationSet {
+    /// the sprite sheet, cut in `columns` by `rows` tiles of `tile_size`.
+    pub path: String,
+    pub tile_size: (f32, f32),
+    pub columns: usize,
+    pub rows: usize,
+    pub clips: HashMap<AnimState, SpriteClip>,
+}
+
+impl AnimationSet {
+    pub fn clip(&self, state: AnimState) -> Option<&SpriteClip> {
+        self.clips
+            .get(&state)
+            .or_else(|| self.clips.get(&AnimState::Idle))
+    }
+}
+
+/// the `first` to `last` tiles of the sheet, at `fps` frames per second.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SpriteClip {
+    pub first: usize,
+    pub last: usize,
+    pub fps: f32,
+    #[serde(default)]
+    pub mode: PlayMode,
+    #[serde(default)]
+    pub events: Vec<FrameEvent>,
+}
+
+impl SpriteClip {
+    pub fn len(&self) -> usize {
+        self.last.saturating_sub(self.first) + 1
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlayMode {
+    #[default]
+    Loop,
+    /// plays once and holds the last frame.
+    Once,
+}
+
+/// frame event of the attack clip on which the unit's skills leave it, see [`PendingReleases`].
+pub const RELEASE_EVENT: &str = "release";
+
+/// `name` is sent in an [`AnimationFrameEvent`] when the clip reaches its `frame`, counted from 0.
+#[derive(Deserialize, Debug, Clone)]
+pub struct FrameEvent {
+    pub frame: usize,
+    pub name: String,
+}
+
+/// sent when an animated unit reaches a frame with a [`FrameEvent`], like a footstep or a release.
+#[derive(Event, Debug)]
+pub struct AnimationFrameEvent {
+    /// the unit, or the sprite itself when it was left behind by a dead monster.
+    pub entity: Entity,
+    pub state: AnimState,
+    pub name: String,
+}
+
+/**
+ plays the clips of an [`AnimationSet`] on the [`TextureAtlasSprite`] it is on.
+
+ the sprite is a child of its unit, which picks the clip from its [`ActionState`] and [`BattleState`].
+*/
+#[derive(Component, Debug, Clone)]
+pub struct AnimationController {
+    set: Handle<AnimationSet>,
+    state: AnimState,
+    /// a once clip played over the state, like a hit or an attack.
+    one_shot: Option<AnimState>,
+    frame: usize,
+    timer: Timer,
+    finished: bool,
+    /// the clip changed and starts on the next update.
+    restart: bool,
+}
+
+impl AnimationController {
+    pub fn new(set: Handle<AnimationSet>) -> Self {
+        Self {
+            set,
+            state: AnimState::Idle,
+            one_shot: None,
+            frame: 0,
+            timer: Timer::default(),
+            finished: false,
+            restart: true,
+        }
+    }
+
+    /// the state whose clip is playing.
+    pub fn playing(&self) -> AnimState {
+        self.one_shot.unwrap_or(self.state)
+    }
+
+    /// whether a once clip reached its last frame.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn set_state(&mut self, state: AnimState) {
+        if self.state == state {
+            return;
+        }
+        self.state = state;
+        if self.one_shot.is_none() {
+            self.restart = true;
+        }
+    }
+
+    /// plays the clip of `state` once, then goes back to the clip of the state.
+    pub fn play_once(&mut self, state: AnimState) {
+        self.one_shot = Some(state);
+        self.restart = true;
+    }
+}
+
+/// an animated sprite whose atlas is made once its [`AnimationSet`] is loaded.
+#[derive(Component)]
+pub struct PendingAtlas;
+
+#[derive(Bundle)]
+pub struct AnimatedSpriteBundle {
+    pub sheet: SpriteSheetBundle,
+    pub controller: AnimationController,
+    pub pending: PendingAtlas,
+}
+
+impl AnimatedSpriteBundle {
+    /// a sprite playing the clips of the set at `path`, drawn at `size`.
+    pub fn new(server: &AssetServer, path: &str, size: Vec2) -> Self {
+        Self {
+            sheet: SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    custom_size: Some(size),
+                    ..default()
+                },
+                ..default()
+            },
+            controller: AnimationController::new(server.load(path.to_owned())),
+            pending: PendingAtlas,
+        }
+    }
+}
+
+/// the atlas of every loaded [`AnimationSet`], shared by all the sprites of the set.
+#[derive(Resource, Default)]
+pub struct AnimationAtlases(HashMap<AssetId<AnimationSet>, Handle<TextureAtlas>>);
+
+pub fn attach_atlases(
+    mut commands: Commands,
+    sets: Res<Assets<AnimationSet>>,
+    server: Res<AssetServer>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut shared: ResMut<AnimationAtlases>,
+    mut sprites: Query<
+        (
+            Entity,
+            Option<&Parent>,
+            &AnimationController,
+            &mut Handle<TextureAtlas>,
+        ),
+        With<PendingAtlas>,
+    >,
+) {
+    for (ent, parent, controller, mut atlas) in &mut sprites {
+        let Some(set) = sets.get(&controller.set) else {
+            continue;
+        };
+        let releases_on_frame = set
+            .clips
+            .get(&AnimState::Attack)
+            .is_some_and(|clip| clip.events.iter().any(|event| event.name == RELEASE_EVENT));
+        if let (Some(parent), true) = (parent, releases_on_frame) {
+            commands
+                .entity(parent.get())
+                .try_insert(PendingReleases::default());
+        }
+        *atlas = shared
+            .0
+            .entry(controller.set.id())
+            .or_insert_with(|| {
+                atlases.add(TextureAtlas::from_grid(
+                    server.load(&set.path),
+                    Vec2::new(set.tile_size.0, set.tile_size.1),
+                    set.columns,
+                    set.rows,
+                    None,
+                    None,
+                ))
+            })
+            .clone();
+        commands.entity(ent).remove::<PendingAtlas>();
+    }
+}
+
+/**
+ picks the clip of every animated unit.
+
+ death wins over everything, then releasing a skill plays the attack once and getting hit the hit
+ once, over casting, moving or standing idle.
+*/
+pub fn drive_animation_state(
+    sets: Res<Assets<AnimationSet>>,
+    mut damage_evt: EventReader<Damage>,
+    mut released_evt: EventReader<SkillReleased>,
+    units: Query<(&ActionState, &BattleState, &Facing, Has<Dead>)>,
+    mut sprites: Query<(&Parent, &mut AnimationController, &mut TextureAtlasSprite)>,
+) {
+    let hit: HashSet<Entity> = damage_evt.read().map(|d| d.attacked).collect();
+    let attacking: HashSet<Entity> = released_evt.read().map(|r| r.caster).collect();
+    for (parent, mut controller, mut sprite) in &mut sprites {
+        let Ok((a_state, b_state, facing, dead)) = units.get(parent.get()) else {
+            continue;
+        };
+        let Some(set) = sets.get(&controller.set) else {
+            continue;
+        };
+        sprite.flip_x = facing.0.x < 0.;
+
+        if dead {
+            controller.one_shot = None;
+            controller.set_state(AnimState::Death);
+            continue;
+        }
+        let has_clip = |state| set.clips.contains_key(&state);
+        if attacking.contains(&parent.get()) && has_clip(AnimState::Attack) {
+            controller.play_once(AnimState::Attack);
+        } else if hit.contains(&parent.get())
+            && controller.one_shot.is_none()
+            && has_clip(AnimState::Hit)
+        {
+            controller.play_once(AnimState::Hit);
+        }
+
+        let state = match (a_state, b_state) {
+            (ActionState::BATTLE, BattleState::CASTING) => AnimState::Cast,
+            (
+                ActionState::BATTLE,
+                BattleState::MOVE | BattleState::RUNAWAY | BattleState::FLEEING,
+            )
+            | (_, BattleState::EVADING)
+            | (ActionState::MOVE, _) => AnimState::Move,
+            _ => AnimState::Idle,
+        };
+        controller.set_state(state);
+    }
+}
+
+pub fn animate_controllers(
+    time: Res<Time>,
+    sets: Res<Assets<AnimationSet>>,
+    mut frame_evt: EventWriter<AnimationFrameEvent>,
+    mut sprites: Query<(
+        Entity,
+        Option<&Parent>,
+        &mut AnimationController,
+        &mut TextureAtlasSprite,
+    )>,
+) {
+    for (ent, parent, mut controller, mut sprite) in &mut sprites {
+        let Some(set) = sets.get(&controller.set) else {
+            continue;
+        };
+        let owner = parent.map_or(ent, |p| p.get());
+        let mut send_events = |controller: &AnimationController, clip: &SpriteClip| {
+            for event in clip.events.iter().filter(|e| e.frame == controller.frame) {
+                frame_evt.send(AnimationFrameEvent {
+                    entity: owner,
+                    state: controller.playing(),
+                    name: event.name.clone(),
+                });
+            }
+        };
+
+        let mut playing = controller.playing();
+        let Some(mut clip) = set.clip(playing) else {
+            continue;
+        };
+        if controller.restart {
+            controller.restart = false;
+            controller.frame = 0;
+            controller.finished = false;
+            controller.timer = Timer::from_seconds(1. / clip.fps.max(0.01), TimerMode::Repeating);
+            send_events(&controller, clip);
+        } else if !controller.finished {
+            controller.timer.tick(time.delta());
+            for _ in 0..controller.timer.times_finished_this_tick() {
+                if controller.frame + 1 < clip.len() {
+                    controller.frame += 1;
+                } else if clip.mode == PlayMode::Loop {
+                    controller.frame = 0;
+                } else if controller.one_shot.take().is_some() {
+                    // back to the clip of the state, from its start.
+                    playing = controller.playing();
+                    clip = set.clip(playing).unwrap_or(clip);
+                    controller.frame = 0;
+                    controller.timer =
+                        Timer::from_seconds(1. / clip.fps.max(0.01), TimerMode::Repeating);
+                } else {
+                    controller.finished = true;
+                    break;
+                }
+                send_events(&controller, clip);
+            }
+        }
+        sprite.index = clip.first + controller.frame;
+    }
+}
+
+/// a sprite left where a monster died, playing its death clip before going away.
+#[derive(Component)]
+pub struct Corpse;
+
+/// monsters are despawned as soon as they die, their sprite stays behind for the death clip.
+pub fn leave_dying_sprites(
+    mut commands: Commands,
+    sets: Res<Assets<AnimationSet>>,
+    mut died_evt: EventReader<Died>,
+    monsters: Query<&Children, With<Monster>>,
+    sprites: Query<(
+        &AnimationController,
+        &TextureAtlasSprite,
+        &Handle<TextureAtlas>,
+        &GlobalTransform,
+    )>,
+) {
+    for died in died_evt.read() {
+        let Ok(children) = monsters.get(died.entity) else {
+            continue;
+        };
+        for (controller, sprite, atlas, global) in sprites.iter_many(children) {
+            let has_death = sets
+                .get(&controller.set)
+                .is_some_and(|set| set.clips.contains_key(&AnimState::Death));
+            if !has_death {
+                continue;
+            }
+            let mut controller = controller.clone();
+            controller.one_shot = None;
+            controller.set_state(AnimState::Death);
+            commands.spawn((
+                SpriteSheetBundle {
+                    sprite: sprite.clone(),
+                    texture_atlas: atlas.clone(),
+                    transform: global.compute_transform(),
+                    ..default()
+                },
+                controller,
+                Corpse,
+            ));
+        }
+    }
+}
+
+pub fn despawn_finished_corpses(
+    mut commands: Commands,
+    corpses: Query<(Entity, &AnimationController), With<Corpse>>,
+) {
+    for (ent, controller) in &corpses {
+        if controller.is_finished() {
+            commands.entity(ent).despawn();
+        }
+    }
+}
+
+/// logs the frame events, for tuning the clips.
+pub fn log_frame_events(mut frame_evt: EventReader<AnimationFrameEvent>) {
+    for event in frame_evt.read() {
+        debug!(
+            "{:?} {:?} reached {:?}",
+            event.entity, event.state, event.name
+        );
+    }
+}
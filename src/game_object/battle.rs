@@ -3,21 +3,33 @@ use bevy::{
     ecs::{
         component::Component,
         entity::Entity,
-        event::{Event, EventReader},
-        query::{Changed, Or, With, Without},
+        event::{Event, EventReader, EventWriter},
+        query::{Changed, Has, Or, With, Without},
         schedule::{common_conditions::in_state, IntoSystemConfigs},
-        system::{Commands, Query},
+        system::{Commands, Query, Res, SystemParam},
     },
+    hierarchy::{Children, DespawnRecursiveExt},
+    time::Time,
     transform::components::Transform,
 };
+use bevy_rapier2d::geometry::ColliderDisabled;
+use serde::Deserialize;
 
-use crate::{states::ActionState, AppState};
+use crate::{
+    states::{ActionState, BattleState},
+    AppState,
+};
 
 use super::{
-    components::{spawn_damage_popup, CastingBar, HealthBar},
+    buff::{BuffKind, Buffs},
+    building::{closest_hostile_building, Building},
+    chunk::Dormant,
+    components::{spawn_damage_popup, CastingBar, HealthBar, PowerBar, Team, TeamType},
     monster::Monster,
+    physics::{enable_hitbox, Hitbox},
     player::Player,
     projectile::Projectile,
+    skill::SkillInfo,
     Target,
 };
 
@@ -28,9 +40,19 @@ impl Plugin for BattlePlugin {
         app.add_event::<Attacked>()
             .add_event::<Damage>()
             .add_event::<Heal>()
+            .add_event::<Died>()
             .add_systems(
                 Update,
-                (damage, heal, die, attacked, detect_enemy).run_if(in_state(AppState::InGame)),
+                (
+                    damage,
+                    heal,
+                    die,
+                    attacked,
+                    detect_enemy,
+                    regenerate_power,
+                    generate_rage,
+                )
+                    .run_if(in_state(AppState::InGame)),
             );
     }
 }
@@ -42,6 +64,8 @@ pub struct Stat {
     pub level: u32,
     pub detect_range: f32,
     pub attack_range: f32,
+    /// `0.2` makes casts and cooldowns 20% faster.
+    pub haste: f32,
 }
 
 impl Stat {
@@ -55,8 +79,14 @@ impl Stat {
             level,
             detect_range,
             attack_range,
+            haste: 0.,
         }
     }
+
+    /// multiplier for cast times and cooldowns, `bonus` being the haste of active buffs.
+    pub fn haste_scale(&self, bonus: f32) -> f32 {
+        1. / (1. + (self.haste + bonus).max(0.))
+    }
 }
 
 #[derive(Debug)]
@@ -71,6 +101,98 @@ impl Health {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerType {
+    Mana,
+    Rage,
+    Energy,
+}
+
+/**
+ Class resource pool paid by skill costs.
+
+ mana and energy regenerate over time, rage is generated by dealing and taking damage
+ and decays out of battle.
+*/
+#[derive(Component, Debug)]
+pub struct Power {
+    pub power_type: PowerType,
+    pub current: f32,
+    pub max: f32,
+    pub regen: f32,
+}
+
+const RAGE_PER_DAMAGE_DEALT: f32 = 0.25;
+const RAGE_PER_DAMAGE_TAKEN: f32 = 0.5;
+const RAGE_DECAY: f32 = 2.;
+
+impl Power {
+    pub fn mana(max: f32, regen: f32) -> Self {
+        Self {
+            power_type: PowerType::Mana,
+            current: max,
+            max,
+            regen,
+        }
+    }
+
+    pub fn rage() -> Self {
+        Self {
+            power_type: PowerType::Rage,
+            current: 0.,
+            max: 100.,
+            regen: 0.,
+        }
+    }
+
+    pub fn energy() -> Self {
+        Self {
+            power_type: PowerType::Energy,
+            current: 100.,
+            max: 100.,
+            regen: 10.,
+        }
+    }
+
+    pub fn ratio(&self) -> f32 {
+        self.current / self.max
+    }
+
+    pub fn can_pay(&self, cost: f32) -> bool {
+        self.current >= cost
+    }
+
+    /// returns `false` and keeps the pool untouched when it can't pay the cost.
+    pub fn spend(&mut self, cost: f32) -> bool {
+        if !self.can_pay(cost) {
+            return false;
+        }
+        self.current -= cost;
+        true
+    }
+
+    pub fn gain(&mut self, value: f32) {
+        self.current = (self.current + value).clamp(0., self.max);
+    }
+}
+
+pub const MAX_COMBO_POINTS: u32 = 5;
+
+/// points built by rogue strikes and spent by finishers.
+#[derive(Component, Debug, Default)]
+pub struct ComboPoints(pub u32);
+
+impl ComboPoints {
+    pub fn add(&mut self, points: u32) {
+        self.0 = (self.0 + points).min(MAX_COMBO_POINTS);
+    }
+
+    /// empties the pool, returning how many points were spent.
+    pub fn consume(&mut self) -> u32 {
+        std::mem::take(&mut self.0)
+    }
+}
+
 #[derive(Component)]
 pub struct Exp {
     current: f32,
@@ -78,9 +200,30 @@ pub struct Exp {
 }
 
 impl Exp {
+    pub fn new(level: u32) -> Self {
+        let mut exp = Self {
+            current: 0.,
+            max: 0.,
+        };
+        exp.from_level(level);
+        exp
+    }
+
     pub fn from_level(&mut self, level: u32) {
         self.max = level as f32 * level as f32 * 15.5 - 10.5 * level as f32 + 4.5;
     }
+
+    /// adds `amount` to a unit of the given level, returning how many levels it went up.
+    pub fn gain(&mut self, amount: f32, level: u32) -> u32 {
+        self.current += amount;
+        let mut levels = 0;
+        while self.current >= self.max {
+            self.current -= self.max;
+            levels += 1;
+            self.from_level(level + levels);
+        }
+        levels
+    }
 }
 
 #[derive(Event)]
@@ -105,18 +248,30 @@ impl Attacked {
 
 pub fn damage(
     mut damage_evt: EventReader<Damage>,
-    mut players: Query<(Entity, &mut Stat), (With<Player>, Without<Monster>)>,
-    mut monsters: Query<(Entity, &mut Stat), (With<Monster>, Without<Player>)>,
+    mut players: Query<
+        (Entity, &mut Stat, &Buffs),
+        (With<Player>, Without<Monster>, Without<Dead>),
+    >,
+    mut monsters: Query<(Entity, &mut Stat, &Buffs), (With<Monster>, Without<Player>)>,
+    mut buildings: Query<
+        (Entity, &mut Stat, &Buffs),
+        (With<Building>, Without<Player>, Without<Monster>),
+    >,
 ) {
     for d in damage_evt.read() {
-        for (ent, mut stat) in &mut players {
+        for (ent, mut stat, buffs) in &mut players {
             if d.attacked == ent {
-                stat.hp.current -= d.damage;
+                stat.hp.current -= d.damage * buffs.damage_taken();
             }
         }
-        for (ent, mut stat) in &mut monsters {
+        for (ent, mut stat, buffs) in &mut monsters {
             if d.attacked == ent {
-                stat.hp.current -= d.damage;
+                stat.hp.current -= d.damage * buffs.damage_taken();
+            }
+        }
+        for (ent, mut stat, buffs) in &mut buildings {
+            if d.attacked == ent {
+                stat.hp.current -= d.damage * buffs.damage_taken();
             }
         }
     }
@@ -125,18 +280,40 @@ pub fn damage(
 pub fn heal(
     mut commands: Commands,
     mut heal_evt: EventReader<Heal>,
-    mut players: Query<(Entity, &mut Stat, &Transform), (With<Player>, Without<Monster>)>,
-    mut monsters: Query<(Entity, &mut Stat, &Transform), (With<Monster>, Without<Player>)>,
+    mut players: Query<
+        (Entity, &mut Stat, Has<Dead>, Option<&Children>),
+        (With<Player>, Without<Monster>),
+    >,
+    mut monsters: Query<(Entity, &mut Stat), (With<Monster>, Without<Player>)>,
+    mut buildings: Query<(Entity, &mut Stat), (With<Building>, Without<Player>, Without<Monster>)>,
+    hitboxes: Query<Entity, With<Hitbox>>,
 ) {
     for h in heal_evt.read() {
-        for (ent, mut stat, t) in &mut players {
+        for (ent, mut stat, dead, children) in &mut players {
+            if h.healed != ent {
+                continue;
+            }
+            // only a resurrection reaches a corpse, and only a corpse is resurrected.
+            match (&h.heal_type, dead) {
+                (HealType::resurrect, true) => {
+                    stat.hp.current = h.value.min(stat.hp.max);
+                    commands.entity(ent).remove::<(Dead, ColliderDisabled)>();
+                    if let Some(children) = children {
+                        enable_hitbox(&mut commands, children, &hitboxes, true);
+                    }
+                }
+                (HealType::resurrect, false) | (_, true) => {}
+                _ => stat.hp.current = (stat.hp.current + h.value).min(stat.hp.max),
+            }
+        }
+        for (ent, mut stat) in &mut monsters {
             if h.healed == ent {
-                stat.hp.current -= h.value;
+                stat.hp.current = (stat.hp.current + h.value).min(stat.hp.max);
             }
         }
-        for (ent, mut stat, t) in &mut monsters {
+        for (ent, mut stat) in &mut buildings {
             if h.healed == ent {
-                stat.hp.current -= h.value;
+                stat.hp.current = (stat.hp.current + h.value).min(stat.hp.max);
             }
         }
     }
@@ -158,7 +335,7 @@ pub struct Heal {
     pub healed: Entity,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub enum DamageType {
     Melee,
     Magic,
@@ -168,50 +345,114 @@ pub enum DamageType {
 pub enum HealType {
     direct,
     dot,
+    /// brings a dead player back with the healed value as its health.
+    resurrect,
 }
 
+/**
+ sent once by [`die`] when a unit's health runs out.
+
+ readers scheduled after [`die`] still find a dying monster or building in the world.
+*/
+#[derive(Event)]
+pub struct Died {
+    pub entity: Entity,
+}
+
+/// dead players stay on the field as corpses until they are resurrected.
+#[derive(Component, Debug)]
+pub struct Dead;
+
+/// the health, casting and power bars drawn over the units.
+#[derive(SystemParam)]
+pub struct UnitBars<'w, 's> {
+    bars: Query<
+        'w,
+        's,
+        (
+            Entity,
+            Option<&'static HealthBar>,
+            Option<&'static CastingBar>,
+            Option<&'static PowerBar>,
+        ),
+        Or<(With<HealthBar>, With<CastingBar>, With<PowerBar>)>,
+    >,
+}
+
+impl<'w, 's> UnitBars<'w, 's> {
+    /// despawns `unit` with its bars.
+    pub fn despawn_unit(&self, command: &mut Commands, unit: Entity) {
+        for (bar_ent, h_bar, c_bar, p_bar) in &self.bars {
+            let target = h_bar
+                .map(|bar| bar.target)
+                .or(c_bar.map(|bar| bar.target))
+                .or(p_bar.map(|bar| bar.target));
+            if target != Some(Some(unit)) {
+                continue;
+            }
+            if c_bar.is_some() {
+                command.entity(bar_ent).despawn_recursive();
+            } else {
+                command.entity(bar_ent).despawn();
+            }
+        }
+        command.entity(unit).despawn_recursive();
+    }
+}
+
+/**
+ despawns monsters and buildings whose health ran out, with their bars.
+
+ players are turned into [`Dead`] corpses instead, so they can be resurrected.
+*/
 pub fn die(
     mut command: Commands,
     mut entities: Query<
-        (Entity, &Stat),
+        (Entity, &Stat, Has<Player>, Option<&Children>),
         (
-            Or<(With<Monster>, With<Player>)>,
+            Or<(With<Monster>, With<Player>, With<Building>)>,
             Changed<Stat>,
             Without<HealthBar>,
             Without<Projectile>,
+            Without<Dead>,
         ),
     >,
-    mut bars: Query<
-        (Entity, Option<&HealthBar>, Option<&CastingBar>),
-        Or<(With<HealthBar>, With<CastingBar>)>,
-    >,
+    bars: UnitBars,
+    hitboxes: Query<Entity, With<Hitbox>>,
+    mut died_evt: EventWriter<Died>,
 ) {
-    for (ent, stat) in &mut entities {
+    for (ent, stat, is_player, children) in &mut entities {
         if stat.hp.current <= 0. {
-            for (bar_ent, h_bar, c_bar) in &mut bars {
-                if h_bar.is_some() {
-                    if h_bar.unwrap().target == Some(ent) {
-                        command.entity(bar_ent).despawn();
-                    }
-                } else if c_bar.is_some() {
-                    if c_bar.unwrap().target == Some(ent) {
-                        command.entity(bar_ent).despawn();
-                    }
-                } else {
-                    continue;
+            died_evt.send(Died { entity: ent });
+            if is_player {
+                // corpses don't block the way, nor catch clicks and projectiles.
+                command.entity(ent).insert((
+                    Dead,
+                    ColliderDisabled,
+                    Target(None),
+                    ActionState::IDLE,
+                    BattleState::IDLE,
+                    SkillInfo::new(),
+                ));
+                if let Some(children) = children {
+                    enable_hitbox(&mut command, children, &hitboxes, false);
                 }
+                continue;
             }
-            command.entity(ent).despawn();
+            bars.despawn_unit(&mut command, ent);
         }
     }
 }
 
 pub fn attacked(
     mut attacked_evt: EventReader<Attacked>,
-    mut entities: Query<(Entity, &mut Target, &mut ActionState), Or<(With<Monster>, With<Player>)>>,
+    mut entities: Query<
+        (Entity, &mut Target, &mut ActionState, &Buffs),
+        Or<(With<Monster>, With<Player>)>,
+    >,
 ) {
-    for (ent, mut targ, mut a_state) in &mut entities {
-        if targ.0.is_some() {
+    for (ent, mut targ, mut a_state, buffs) in &mut entities {
+        if targ.0.is_some() || buffs.has(BuffKind::Evading) {
             continue;
         }
         //if target is None,
@@ -226,12 +467,20 @@ pub fn attacked(
 
 pub fn detect_enemy(
     mut commands: Commands,
-    mut players: Query<(Entity, &Transform, &Stat, &mut Target), (With<Player>, Without<Monster>)>,
-    mut monsters: Query<(Entity, &Transform, &Stat, &mut Target), (With<Monster>, Without<Player>)>,
+    mut players: Query<
+        (Entity, &Transform, &Stat, &mut Target, &Buffs),
+        (With<Player>, Without<Monster>, Without<Dead>),
+    >,
+    mut monsters: Query<
+        (Entity, &Transform, &Stat, &mut Target, &Buffs),
+        (With<Monster>, Without<Player>, Without<Dormant>),
+    >,
+    buildings: Query<(Entity, &Transform, &Team), With<Building>>,
+    dead: Query<(), With<Dead>>,
 ) {
     //players
-    for (ent, t, stat, mut target) in &mut players {
-        for (m_ent, m_t, m_stat, m_target) in &mut monsters {
+    for (ent, t, stat, mut target, _) in &mut players {
+        for (m_ent, m_t, m_stat, m_target, _) in &mut monsters {
             if target.0 == None {
                 let mut _target: Option<Entity> = None;
                 let mut min = f32::MAX;
@@ -248,16 +497,38 @@ pub fn detect_enemy(
 
                 *target = Target(_target);
             } else {
-                if commands.get_entity(target.0.unwrap()).is_none() {
+                let targ = target.0.unwrap();
+                if commands.get_entity(targ).is_none() || dead.contains(targ) {
                     *target = Target(None);
                 }
             }
         }
+        // with no monster around, hostile buildings in range are attacked instead.
+        if target
+            .0
+            .is_none_or(|targ| commands.get_entity(targ).is_none())
+        {
+            *target = Target(closest_hostile_building(
+                &buildings,
+                t.translation,
+                stat.detect_range,
+                TeamType::PLAYER,
+            ));
+        }
     }
 
-    for (ent, t, stat, mut target) in &mut monsters {
-        for (p_ent, p_t, p_stat, p_target) in &mut players {
+    for (ent, t, stat, mut target, buffs) in &mut monsters {
+        // evading monsters ignore everybody until they are back home.
+        if buffs.has(BuffKind::Evading) {
+            continue;
+        }
+        for (p_ent, p_t, p_stat, p_target, p_buffs) in &mut players {
             if target.0 == None {
+                // stealthed players can't be detected.
+                if p_buffs.has(BuffKind::Stealth) {
+                    continue;
+                }
+
                 let mut _target: Option<Entity> = None;
                 let mut min = f32::MAX;
 
@@ -273,10 +544,56 @@ pub fn detect_enemy(
 
                 *target = Target(_target);
             } else {
-                if commands.get_entity(target.0.unwrap()).is_none() {
+                let targ = target.0.unwrap();
+                if commands.get_entity(targ).is_none() || dead.contains(targ) {
                     *target = Target(None);
                 }
             }
         }
+        if target
+            .0
+            .is_none_or(|targ| commands.get_entity(targ).is_none())
+        {
+            *target = Target(closest_hostile_building(
+                &buildings,
+                t.translation,
+                stat.detect_range,
+                TeamType::MONSTER,
+            ));
+        }
+    }
+}
+
+pub fn regenerate_power(
+    time: Res<Time>,
+    mut entities: Query<(&mut Power, &ActionState), Without<Dead>>,
+) {
+    for (mut power, a_state) in &mut entities {
+        match power.power_type {
+            PowerType::Mana | PowerType::Energy => {
+                let regen = power.regen * time.delta_seconds();
+                power.gain(regen);
+            }
+            PowerType::Rage => {
+                if *a_state != ActionState::BATTLE {
+                    power.gain(-RAGE_DECAY * time.delta_seconds());
+                }
+            }
+        }
+    }
+}
+
+pub fn generate_rage(mut damage_evt: EventReader<Damage>, mut entities: Query<&mut Power>) {
+    for d in damage_evt.read() {
+        if let Ok(mut power) = entities.get_mut(d.attacker) {
+            if power.power_type == PowerType::Rage {
+                power.gain(d.damage * RAGE_PER_DAMAGE_DEALT);
+            }
+        }
+        if let Ok(mut power) = entities.get_mut(d.attacked) {
+            if power.power_type == PowerType::Rage {
+                power.gain(d.damage * RAGE_PER_DAMAGE_TAKEN);
+            }
+        }
     }
 }
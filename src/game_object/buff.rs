@@ -0,0 +1,208 @@
+use bevy::{
+    app::{App, Plugin, Update},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res},
+    },
+    time::{Time, Timer, TimerMode},
+};
+use serde::Deserialize;
+
+use crate::AppState;
+
+use super::{
+    battle::{Damage, Heal, HealType},
+    Target,
+};
+
+pub struct BuffPlugin;
+
+impl Plugin for BuffPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (tick_buffs, force_taunt_target, break_stealth).run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuffKind {
+    ArcanePower,
+    ShieldBlock,
+    /// forces the unit to target the buff's source.
+    Taunted,
+    Renew,
+    /// hidden from enemy detection until the unit deals damage.
+    Stealth,
+    Sanctuary,
+    /// a leashed monster walking back to its spawn, immune to damage.
+    Evading,
+    /// runs away from its target instead of fighting.
+    Fleeing,
+    /// a boss past its enrage timer, casting twice as fast.
+    Enraged,
+    /// tank swap debuff of a boss, the tank takes more damage until it runs out.
+    Sundered,
+}
+
+impl BuffKind {
+    /// bonus added to [`super::battle::Stat::haste`] while the buff is up.
+    pub fn haste(&self) -> f32 {
+        match self {
+            BuffKind::ArcanePower => 0.3,
+            BuffKind::Enraged => 1.,
+            _ => 0.,
+        }
+    }
+
+    /// multiplier applied to incoming damage while the buff is up.
+    pub fn damage_taken(&self) -> f32 {
+        match self {
+            BuffKind::ShieldBlock => 0.4,
+            BuffKind::Sanctuary => 0.8,
+            BuffKind::Evading => 0.,
+            BuffKind::Sundered => 1.5,
+            _ => 1.,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Buff {
+    pub kind: BuffKind,
+    pub source: Entity,
+    /// `None` for a buff that stays until it is removed.
+    pub timer: Option<Timer>,
+    pub periodic: Option<Periodic>,
+}
+
+/// heal applied by the buff every time `interval` finishes.
+#[derive(Debug)]
+pub struct Periodic {
+    pub interval: Timer,
+    pub heal: f32,
+}
+
+/// buffs and debuffs currently applied to a unit.
+#[derive(Component, Default, Debug)]
+pub struct Buffs(Vec<Buff>);
+
+impl Buffs {
+    pub fn has(&self, kind: BuffKind) -> bool {
+        self.0.iter().any(|buff| buff.kind == kind)
+    }
+
+    pub fn get(&self, kind: BuffKind) -> Option<&Buff> {
+        self.0.iter().find(|buff| buff.kind == kind)
+    }
+
+    /// applies the buff, refreshing its duration when it is already up.
+    pub fn add(&mut self, kind: BuffKind, source: Entity, duration: f32) {
+        self.remove(kind);
+        self.0.push(Buff {
+            kind,
+            source,
+            timer: Some(Timer::from_seconds(duration, TimerMode::Once)),
+            periodic: None,
+        });
+    }
+
+    /// applies the buff until it is removed.
+    pub fn add_permanent(&mut self, kind: BuffKind, source: Entity) {
+        self.remove(kind);
+        self.0.push(Buff {
+            kind,
+            source,
+            timer: None,
+            periodic: None,
+        });
+    }
+
+    pub fn add_periodic(
+        &mut self,
+        kind: BuffKind,
+        source: Entity,
+        duration: f32,
+        interval: f32,
+        heal: f32,
+    ) {
+        self.remove(kind);
+        self.0.push(Buff {
+            kind,
+            source,
+            timer: Some(Timer::from_seconds(duration, TimerMode::Once)),
+            periodic: Some(Periodic {
+                interval: Timer::from_seconds(interval, TimerMode::Repeating),
+                heal,
+            }),
+        });
+    }
+
+    pub fn remove(&mut self, kind: BuffKind) {
+        self.0.retain(|buff| buff.kind != kind);
+    }
+
+    pub fn haste(&self) -> f32 {
+        self.0.iter().map(|buff| buff.kind.haste()).sum()
+    }
+
+    pub fn damage_taken(&self) -> f32 {
+        self.0.iter().map(|buff| buff.kind.damage_taken()).product()
+    }
+}
+
+pub fn tick_buffs(
+    time: Res<Time>,
+    mut heal_evt: EventWriter<Heal>,
+    mut entities: Query<(Entity, &mut Buffs)>,
+) {
+    for (ent, mut buffs) in &mut entities {
+        for buff in buffs.0.iter_mut() {
+            if let Some(timer) = buff.timer.as_mut() {
+                timer.tick(time.delta());
+            }
+            let Some(periodic) = buff.periodic.as_mut() else {
+                continue;
+            };
+            periodic.interval.tick(time.delta());
+            for _ in 0..periodic.interval.times_finished_this_tick() {
+                heal_evt.send(Heal {
+                    healer: buff.source,
+                    value: periodic.heal,
+                    heal_type: HealType::dot,
+                    healed: ent,
+                });
+            }
+        }
+        buffs
+            .0
+            .retain(|buff| !buff.timer.as_ref().is_some_and(Timer::finished));
+    }
+}
+
+/// taunted units keep their [`Target`] on the taunter until the buff runs out.
+pub fn force_taunt_target(mut commands: Commands, mut entities: Query<(&Buffs, &mut Target)>) {
+    for (buffs, mut target) in &mut entities {
+        let Some(taunt) = buffs.get(BuffKind::Taunted) else {
+            continue;
+        };
+        if target.0 != Some(taunt.source) && commands.get_entity(taunt.source).is_some() {
+            *target = Target(Some(taunt.source));
+        }
+    }
+}
+
+/// dealing damage reveals a stealthed unit.
+pub fn break_stealth(mut damage_evt: EventReader<Damage>, mut entities: Query<&mut Buffs>) {
+    for d in damage_evt.read() {
+        if let Ok(mut buffs) = entities.get_mut(d.attacker) {
+            if buffs.has(BuffKind::Stealth) {
+                buffs.remove(BuffKind::Stealth);
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::Assets,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::With,
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    input::{keyboard::KeyCode, mouse::MouseButton, Input},
+    log::info,
+    math::{Vec2, Vec3},
+    prelude::default,
+    render::{camera::Camera, color::Color},
+    sprite::{Sprite, SpriteBundle},
+    transform::components::{GlobalTransform, Transform},
+    window::{PrimaryWindow, Window},
+};
+use bevy_rapier2d::plugin::RapierContext;
+
+use crate::{resources::resource::Stockpile, AppState};
+
+use super::{
+    building::{construct_building, BlueprintBook, Blueprints},
+    camera::select_gameobject,
+    chunk::ChunkedTiles,
+    command::order_move,
+    components::TeamType,
+    physics::blocked_at,
+    render_layer::RenderLayer,
+};
+
+pub struct BuildModePlugin;
+
+impl Plugin for BuildModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BuildMode>().add_systems(
+            Update,
+            (toggle_build_mode, move_build_ghost, confirm_building)
+                .chain()
+                // clicks of the build mode are not orders nor selections.
+                .after(order_move)
+                .after(select_gameobject)
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// level of the buildings the players build.
+const BUILDING_LEVEL: u32 = 1;
+/// tile size of the grid when the world has no tiles.
+const DEFAULT_GRID: f32 = 32.;
+
+/**
+ The blueprint the player is placing, `None` out of build mode.
+
+ `B` enters and leaves the build mode, `Tab` picks the next blueprint, `Escape` or a right click
+ cancels. a left click builds where the ghost stands, holding shift to keep on building.
+*/
+#[derive(Resource, Default, Debug)]
+pub struct BuildMode {
+    pub blueprint: Option<String>,
+}
+
+impl BuildMode {
+    pub fn is_active(&self) -> bool {
+        self.blueprint.is_some()
+    }
+}
+
+/// translucent preview of the building under the cursor, red where it can't be built or paid for.
+#[derive(Component, Debug)]
+pub struct BuildGhost {
+    pub valid: bool,
+}
+
+/// center of a box of `size` under `cursor`, its sides on the lines of the grid.
+fn snap_to_grid(cursor: Vec2, size: Vec2, grid: Option<(Vec2, Vec2)>) -> Vec2 {
+    let (origin, tile) = grid.unwrap_or((Vec2::ZERO, Vec2::splat(DEFAULT_GRID)));
+    // tiles covered by the box, centering it on the cursor at best.
+    let tiles = (size / tile).ceil().max(Vec2::ONE);
+    let corner = ((cursor - origin) / tile - tiles / 2.).round();
+    origin + (corner + tiles / 2.) * tile
+}
+
+pub fn toggle_build_mode(
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    mut build_mode: ResMut<BuildMode>,
+    book: Res<BlueprintBook>,
+    blueprints: Res<Assets<Blueprints>>,
+) {
+    let ids = book.ids(&blueprints);
+    let next = if keys.just_pressed(KeyCode::B) {
+        match build_mode.blueprint {
+            Some(_) => None,
+            None => ids.first().map(|id| id.to_string()),
+        }
+    } else if !build_mode.is_active() {
+        return;
+    } else if keys.just_pressed(KeyCode::Escape) || mouse.just_pressed(MouseButton::Right) {
+        None
+    } else if keys.just_pressed(KeyCode::Tab) {
+        let current = ids
+            .iter()
+            .position(|id| Some(*id) == build_mode.blueprint.as_ref());
+        let index = current.map_or(0, |i| (i + 1) % ids.len());
+        ids.get(index).map(|id| id.to_string())
+    } else {
+        return;
+    };
+    info!("build mode : {:?}", next);
+    build_mode.blueprint = next;
+}
+
+/// puts the ghost of the blueprint under the cursor, green where the building fits and is paid for.
+pub fn move_build_ghost(
+    mut commands: Commands,
+    build_mode: Res<BuildMode>,
+    book: Res<BlueprintBook>,
+    blueprints: Res<Assets<Blueprints>>,
+    tiles: Res<ChunkedTiles>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    cam_q: Query<(&Camera, &GlobalTransform), With<Camera>>,
+    rapier_context: Res<RapierContext>,
+    stockpile: Res<Stockpile>,
+    mut ghosts: Query<(Entity, &mut BuildGhost, &mut Transform, &mut Sprite)>,
+) {
+    let blueprint = build_mode
+        .blueprint
+        .as_ref()
+        .and_then(|id| book.get(id, &blueprints));
+    let Some(blueprint) = blueprint else {
+        for (ent, ..) in &ghosts {
+            commands.entity(ent).despawn();
+        }
+        return;
+    };
+    let (cam, cam_t) = cam_q.single();
+    let Some(mouse_pos) = window_q
+        .single()
+        .cursor_position()
+        .and_then(|cursor| cam.viewport_to_world_2d(cam_t, cursor))
+    else {
+        return;
+    };
+
+    let footprint = blueprint.footprint();
+    let center = snap_to_grid(mouse_pos, footprint, tiles.grid());
+    let valid =
+        !blocked_at(&rapier_context, center, footprint) && stockpile.can_pay(&blueprint.cost);
+    let color = if valid {
+        Color::rgba(0.2, 1., 0.2, 0.5)
+    } else {
+        Color::rgba(1., 0.2, 0.2, 0.5)
+    };
+    let size = Vec2::new(blueprint.size.0, blueprint.size.1);
+    let translation = Vec3::from((center, RenderLayer::Overhead.z()));
+    match ghosts.get_single_mut() {
+        Ok((_, mut ghost, mut transform, mut sprite)) => {
+            ghost.valid = valid;
+            transform.translation = translation;
+            sprite.color = color;
+            sprite.custom_size = Some(size);
+        }
+        Err(_) => {
+            commands.spawn((
+                BuildGhost { valid },
+                SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        custom_size: Some(size),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(translation),
+                    ..default()
+                },
+            ));
+        }
+    }
+}
+
+/// starts the construction of the blueprint where the ghost stands, paid from the stockpile.
+pub fn confirm_building(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    mut build_mode: ResMut<BuildMode>,
+    book: Res<BlueprintBook>,
+    blueprints: Res<Assets<Blueprints>>,
+    mut stockpile: ResMut<Stockpile>,
+    ghosts: Query<(&BuildGhost, &Transform)>,
+) {
+    if !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some(id) = build_mode.blueprint.clone() else {
+        return;
+    };
+    let (Some(blueprint), Ok((ghost, ghost_t))) = (book.get(&id, &blueprints), ghosts.get_single())
+    else {
+        return;
+    };
+    if !stockpile.can_pay(&blueprint.cost) {
+        info!(
+            "not enough items for {} : {:?}",
+            blueprint.name, blueprint.cost
+        );
+        return;
+    }
+    if !ghost.valid || !stockpile.pay(&blueprint.cost) {
+        info!("can't build {} here", blueprint.name);
+        return;
+    }
+    let position = ghost_t.translation.truncate().extend(0.);
+    construct_building(
+        &mut commands,
+        id,
+        TeamType::PLAYER,
+        BUILDING_LEVEL,
+        Transform::from_translation(position),
+    );
+    info!("building {} at {:?}", blueprint.name, position);
+    if !keys.pressed(KeyCode::ShiftLeft) {
+        build_mode.blueprint = None;
+    }
+}
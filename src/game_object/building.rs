@@ -0,0 +1,505 @@
+use std::collections::HashMap;
+
+use bevy::{
+    app::{App, Plugin, Startup, Update},
+    asset::{Asset, AssetApp, AssetServer, Assets, Handle},
+    ecs::{
+        bundle::Bundle,
+        component::Component,
+        entity::Entity,
+        event::{Event, EventReader, EventWriter},
+        query::{Or, With, Without},
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    log::{info, warn},
+    math::{Vec2, Vec3},
+    prelude::default,
+    reflect::TypePath,
+    render::color::Color,
+    sprite::{Sprite, SpriteBundle},
+    time::{Time, Timer, TimerMode},
+    transform::components::Transform,
+};
+use serde::Deserialize;
+
+use crate::{data::RonLoader, AppState};
+
+use super::{
+    battle::{die, Dead, Died, Heal, HealType, Stat},
+    buff::{BuffKind, Buffs},
+    components::{Team, TeamType},
+    monster::Monster,
+    physics::{spawn_hitbox, ObstacleBundle},
+    player::Player,
+    render_layer::YSort,
+    skill::{release_skill, SkillBook, SkillCode, SkillHit},
+    skill_definition::{scaled, SkillDefinition},
+    system::spawn_healthbar,
+    Facing, Target,
+};
+
+pub struct BuildingPlugin;
+
+impl Plugin for BuildingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Blueprints>()
+            .register_asset_loader(RonLoader::<Blueprints>::new(&["blueprints.ron"]))
+            .init_resource::<BlueprintBook>()
+            .add_event::<BuildingDestroyed>()
+            .add_systems(Startup, load_blueprint_book)
+            .add_systems(
+                Update,
+                (
+                    spawn_pending_buildings,
+                    tower_attack,
+                    shrine_heal,
+                    progress_construction,
+                    (destroy_buildings.after(die), announce_destroyed_buildings).chain(),
+                )
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/**
+ Every [`Blueprint`] by id, loaded from `buildings/buildings.blueprints.ron`.
+
+ map files place buildings by their id in this file.
+*/
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct Blueprints(pub HashMap<String, Blueprint>);
+
+/// Data driven description of a kind of building, scaled to the level it is built at.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Blueprint {
+    pub name: String,
+    pub hp: f32,
+    #[serde(default)]
+    pub hp_per_level: f32,
+    /// drawn size, centered on the building's position.
+    pub size: (f32, f32),
+    /// `(r, g, b)` of the sprite, or its tint when it has an `image`.
+    #[serde(default = "white")]
+    pub color: (f32, f32, f32),
+    #[serde(default)]
+    pub image: Option<String>,
+    /// blocking box around the position, the whole sprite when `None`.
+    #[serde(default)]
+    pub footprint: Option<(f32, f32)>,
+    #[serde(default)]
+    pub behavior: Option<BuildingBehavior>,
+    /// items of the stockpile spent to build it, by name.
+    #[serde(default)]
+    pub cost: HashMap<String, u32>,
+    /// seconds of construction when the players build it, maps place it finished.
+    #[serde(default)]
+    pub build_time: f32,
+}
+
+fn white() -> (f32, f32, f32) {
+    (1., 1., 1.)
+}
+
+impl Blueprint {
+    pub fn hp(&self, level: u32) -> f32 {
+        scaled(self.hp, self.hp_per_level, level)
+    }
+
+    pub fn footprint(&self) -> Vec2 {
+        let (x, y) = self.footprint.unwrap_or(self.size);
+        Vec2::new(x, y)
+    }
+}
+
+/// what a building does on its own.
+#[derive(Deserialize, Debug, Clone)]
+pub enum BuildingBehavior {
+    /// casts `skill` on the closest hostile unit within `range`, every `interval` seconds.
+    Tower {
+        skill: SkillCode,
+        range: f32,
+        interval: f32,
+    },
+    /// heals every hurt ally within `radius` by `amount`, every `interval` seconds.
+    Shrine {
+        radius: f32,
+        amount: f32,
+        #[serde(default)]
+        per_level: f32,
+        interval: f32,
+    },
+}
+
+/// a structure standing in the world, damaged and destroyed like any unit.
+#[derive(Component, Debug)]
+pub struct Building {
+    pub name: String,
+}
+
+#[derive(Bundle)]
+pub struct BuildingBundle {
+    pub building: Building,
+    pub stat: Stat,
+    pub team: Team,
+    /// targeted by skills like units are, so it needs their buffs, target and facing.
+    pub buffs: Buffs,
+    pub target: Target,
+    pub facing: Facing,
+    pub sprite: SpriteBundle,
+    pub obstacle: ObstacleBundle,
+    pub y_sort: YSort,
+}
+
+#[derive(Component, Debug)]
+pub struct Tower {
+    pub skill: SkillCode,
+    pub range: f32,
+    pub timer: Timer,
+}
+
+#[derive(Component, Debug)]
+pub struct Shrine {
+    pub radius: f32,
+    pub amount: f32,
+    pub timer: Timer,
+}
+
+/// share of its health a building has when its construction starts.
+const CONSTRUCTION_START_HP: f32 = 0.1;
+
+/**
+ A building the players are still building.
+
+ its health grows to the full over the timer, damage taken meanwhile staying taken.
+ towers and shrines don't do anything before they're finished.
+*/
+#[derive(Component, Debug)]
+pub struct Construction {
+    pub timer: Timer,
+}
+
+/// a building waiting for the blueprints to be loaded.
+#[derive(Component)]
+pub struct PendingBuilding {
+    id: String,
+    team: TeamType,
+    level: u32,
+    /// starts as a [`Construction`] when the blueprint has a build time.
+    construct: bool,
+}
+
+/// Handle of the [`Blueprints`] file.
+#[derive(Resource, Default)]
+pub struct BlueprintBook {
+    blueprints: Handle<Blueprints>,
+}
+
+impl BlueprintBook {
+    /// ids of every blueprint in alphabetical order, empty until the file is loaded.
+    pub fn ids<'a>(&self, blueprints: &'a Assets<Blueprints>) -> Vec<&'a String> {
+        let mut ids: Vec<&String> = blueprints
+            .get(&self.blueprints)
+            .map(|blueprints| blueprints.0.keys().collect())
+            .unwrap_or_default();
+        ids.sort();
+        ids
+    }
+
+    pub fn get<'a>(&self, id: &str, blueprints: &'a Assets<Blueprints>) -> Option<&'a Blueprint> {
+        blueprints
+            .get(&self.blueprints)
+            .and_then(|blueprints| blueprints.0.get(id))
+    }
+}
+
+pub fn load_blueprint_book(mut book: ResMut<BlueprintBook>, server: Res<AssetServer>) {
+    book.blueprints = server.load("buildings/buildings.blueprints.ron");
+}
+
+/// sent when the health of a building runs out, before it is despawned.
+#[derive(Event)]
+pub struct BuildingDestroyed {
+    pub name: String,
+    pub team: TeamType,
+    pub position: Vec3,
+}
+
+/// places the finished building `id` of the blueprints for `team`, centered on `transform`.
+pub fn place_building(
+    commands: &mut Commands,
+    id: impl Into<String>,
+    team: TeamType,
+    level: u32,
+    transform: Transform,
+) -> Entity {
+    commands
+        .spawn((
+            PendingBuilding {
+                id: id.into(),
+                team,
+                level,
+                construct: false,
+            },
+            transform,
+        ))
+        .id()
+}
+
+/// like [`place_building`], but the building starts as a [`Construction`].
+pub fn construct_building(
+    commands: &mut Commands,
+    id: impl Into<String>,
+    team: TeamType,
+    level: u32,
+    transform: Transform,
+) -> Entity {
+    commands
+        .spawn((
+            PendingBuilding {
+                id: id.into(),
+                team,
+                level,
+                construct: true,
+            },
+            transform,
+        ))
+        .id()
+}
+
+pub fn spawn_pending_buildings(
+    mut commands: Commands,
+    book: Res<BlueprintBook>,
+    blueprints: Res<Assets<Blueprints>>,
+    server: Res<AssetServer>,
+    pending: Query<(Entity, &PendingBuilding, &Transform)>,
+) {
+    if blueprints.get(&book.blueprints).is_none() {
+        return;
+    }
+    for (ent, pending, transform) in &pending {
+        let Some(blueprint) = book.get(&pending.id, &blueprints) else {
+            warn!("no building {:?}", pending.id);
+            commands.entity(ent).despawn();
+            continue;
+        };
+        let size = Vec2::new(blueprint.size.0, blueprint.size.1);
+        let (r, g, b) = blueprint.color;
+        let hp = blueprint.hp(pending.level);
+        let mut stat = Stat::new(0., hp, pending.level, 0., 0.);
+        let constructing = pending.construct && blueprint.build_time > 0.;
+        let mut color = Color::rgb(r, g, b);
+        if constructing {
+            stat.hp.current = hp * CONSTRUCTION_START_HP;
+            color.set_a(0.5);
+            commands.entity(ent).insert(Construction {
+                timer: Timer::from_seconds(blueprint.build_time, TimerMode::Once),
+            });
+        }
+        commands
+            .entity(ent)
+            .remove::<PendingBuilding>()
+            .insert(BuildingBundle {
+                building: Building {
+                    name: blueprint.name.clone(),
+                },
+                stat,
+                team: Team(pending.team),
+                buffs: Buffs::default(),
+                target: Target(None),
+                facing: Facing::default(),
+                sprite: SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        custom_size: Some(size),
+                        ..default()
+                    },
+                    texture: blueprint
+                        .image
+                        .as_ref()
+                        .map(|path| server.load(path))
+                        .unwrap_or_default(),
+                    transform: *transform,
+                    ..default()
+                },
+                obstacle: ObstacleBundle::new(blueprint.footprint(), Vec2::ZERO),
+                y_sort: YSort::feet(size.y),
+            });
+        match &blueprint.behavior {
+            Some(BuildingBehavior::Tower {
+                skill,
+                range,
+                interval,
+            }) => {
+                commands.entity(ent).insert(Tower {
+                    skill: *skill,
+                    range: *range,
+                    timer: Timer::from_seconds(*interval, TimerMode::Repeating),
+                });
+            }
+            Some(BuildingBehavior::Shrine {
+                radius,
+                amount,
+                per_level,
+                interval,
+            }) => {
+                commands.entity(ent).insert(Shrine {
+                    radius: *radius,
+                    amount: scaled(*amount, *per_level, pending.level),
+                    timer: Timer::from_seconds(*interval, TimerMode::Repeating),
+                });
+            }
+            None => {}
+        }
+        let t = Transform::from_xyz(10000., 10000., 10000.);
+        spawn_healthbar(&mut commands, ent, t);
+        spawn_hitbox(&mut commands, ent, size);
+    }
+}
+
+/// the closest building of another team than `team` within `range` of `position`.
+pub fn closest_hostile_building(
+    buildings: &Query<(Entity, &Transform, &Team), With<Building>>,
+    position: Vec3,
+    range: f32,
+    team: TeamType,
+) -> Option<Entity> {
+    buildings
+        .iter()
+        .filter(|(_, _, b_team)| b_team.0 != team)
+        .map(|(ent, t, _)| (ent, t.translation.distance(position)))
+        .filter(|(_, distance)| *distance <= range)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(ent, _)| ent)
+}
+
+/// towers shoot at the closest hostile unit in range, stealthed units are not seen.
+pub fn tower_attack(
+    mut commands: Commands,
+    time: Res<Time>,
+    skill_book: Res<SkillBook>,
+    definitions: Res<Assets<SkillDefinition>>,
+    mut hit_evt: EventWriter<SkillHit>,
+    mut towers: Query<
+        (Entity, &Transform, &Stat, &Team, &mut Tower, &mut Target),
+        Without<Construction>,
+    >,
+    units: Query<
+        (Entity, &Transform, &Team, &Buffs),
+        (Or<(With<Player>, With<Monster>)>, Without<Dead>),
+    >,
+) {
+    for (ent, t, stat, team, mut tower, mut target) in &mut towers {
+        tower.timer.tick(time.delta());
+        let closest = units
+            .iter()
+            .filter(|(_, _, u_team, buffs)| u_team.0 != team.0 && !buffs.has(BuffKind::Stealth))
+            .map(|(u_ent, u_t, _, _)| (u_ent, u_t, u_t.translation.distance(t.translation)))
+            .filter(|(_, _, distance)| *distance <= tower.range)
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+        *target = Target(closest.map(|(u_ent, _, _)| u_ent));
+        let Some((targ_ent, targ_t, _)) = closest else {
+            continue;
+        };
+        if !tower.timer.just_finished() {
+            continue;
+        }
+        let Some(definition) = skill_book.get(&tower.skill, &definitions) else {
+            continue;
+        };
+        release_skill(
+            &mut commands,
+            &mut hit_evt,
+            definition,
+            SkillHit {
+                caster: ent,
+                target: targ_ent,
+                skill: tower.skill,
+                level: stat.level,
+            },
+            team.0,
+            t,
+            targ_t,
+        );
+    }
+}
+
+pub fn shrine_heal(
+    time: Res<Time>,
+    mut shrines: Query<(Entity, &Transform, &Team, &mut Shrine), Without<Construction>>,
+    units: Query<
+        (Entity, &Transform, &Team, &Stat),
+        (Or<(With<Player>, With<Monster>)>, Without<Dead>),
+    >,
+    mut heal_evt: EventWriter<Heal>,
+) {
+    for (ent, t, team, mut shrine) in &mut shrines {
+        shrine.timer.tick(time.delta());
+        if !shrine.timer.just_finished() {
+            continue;
+        }
+        for (u_ent, u_t, u_team, stat) in &units {
+            if u_team.0 != team.0
+                || stat.hp.current >= stat.hp.max
+                || u_t.translation.distance(t.translation) > shrine.radius
+            {
+                continue;
+            }
+            heal_evt.send(Heal {
+                healer: ent,
+                value: shrine.amount,
+                heal_type: HealType::dot,
+                healed: u_ent,
+            });
+        }
+    }
+}
+
+/// grows the health of the buildings under construction, and finishes them when their time is up.
+pub fn progress_construction(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut buildings: Query<(Entity, &Building, &mut Construction, &mut Stat, &mut Sprite)>,
+) {
+    for (ent, building, mut construction, mut stat, mut sprite) in &mut buildings {
+        construction.timer.tick(time.delta());
+        let duration = construction.timer.duration().as_secs_f32();
+        let gain = stat.hp.max * (1. - CONSTRUCTION_START_HP) * time.delta_seconds() / duration;
+        if stat.hp.current > 0. {
+            stat.hp.current = (stat.hp.current + gain).min(stat.hp.max);
+        }
+        if construction.timer.finished() {
+            commands.entity(ent).remove::<Construction>();
+            sprite.color.set_a(1.);
+            info!("{} built", building.name);
+        }
+    }
+}
+
+/// reports the buildings whose health ran out, [`super::battle::die`] despawns them.
+pub fn destroy_buildings(
+    mut died_evt: EventReader<Died>,
+    buildings: Query<(&Building, &Team, &Transform)>,
+    mut destroyed_evt: EventWriter<BuildingDestroyed>,
+) {
+    for died in died_evt.read() {
+        let Ok((building, team, t)) = buildings.get(died.entity) else {
+            continue;
+        };
+        destroyed_evt.send(BuildingDestroyed {
+            name: building.name.clone(),
+            team: team.0,
+            position: t.translation,
+        });
+    }
+}
+
+/// logs the destroyed buildings.
+pub fn announce_destroyed_buildings(mut destroyed_evt: EventReader<BuildingDestroyed>) {
+    for destroyed in destroyed_evt.read() {
+        info!(
+            "{} of {:?} destroyed at {:?}",
+            destroyed.name, destroyed.team, destroyed.position
+        );
+    }
+}
@@ -1,30 +1,36 @@
 use bevy::{
     app::{App, Plugin, Update},
     ecs::{
-        entity::Entity,
         event::EventReader,
-        query::{Or, With, Without},
+        query::{With, Without},
         schedule::{common_conditions::in_state, IntoSystemConfigs},
         system::{Query, Res, ResMut},
     },
+    hierarchy::Parent,
     input::{
         keyboard::{KeyCode, KeyboardInput},
         mouse::{MouseButtonInput, MouseWheel},
         ButtonState, Input,
     },
     log::info,
-    math::{Vec2, Vec3},
+    math::Vec3,
     render::camera::{Camera, OrthographicProjection},
-    sprite::{collide_aabb::collide, Sprite},
     time::Time,
     transform::components::{GlobalTransform, Transform},
     window::{PrimaryWindow, Window},
 };
+use bevy_rapier2d::plugin::RapierContext;
 // use web_sys::KeyboardEvent;
 
 use crate::{resources::resource::SelectedList, AppState};
 
-use super::{monster::Monster, player::Player, Target};
+use super::{
+    build_mode::BuildMode,
+    monster::Monster,
+    physics::{units_at, Hitbox},
+    player::Player,
+    Target,
+};
 
 pub struct CamPlugin;
 
@@ -98,11 +104,14 @@ pub fn select_gameobject(
     mut keys: Res<Input<KeyCode>>,
     window_q: Query<&Window, With<PrimaryWindow>>,
     mut cam_q: Query<(&Camera, &GlobalTransform), With<Camera>>,
-    mut entities: Query<
-        (Entity, &GlobalTransform, &Sprite),
-        (Or<(With<Monster>, With<Player>)>, Without<Camera>),
-    >,
+    rapier_context: Res<RapierContext>,
+    hitboxes: Query<&Parent, With<Hitbox>>,
+    build_mode: Res<BuildMode>,
 ) {
+    if build_mode.is_active() {
+        mouse_event.clear();
+        return;
+    }
     for ev in mouse_event.read() {
         match ev.state {
             ButtonState::Released => {
@@ -115,28 +124,15 @@ pub fn select_gameobject(
                         return;
                     };
                     // let mut entity_select = false;
-                    for (ent, ent_t, ent_sprite) in &mut entities {
-                        if collide(
-                            Vec3::new(mouse_pos.x, mouse_pos.y, 0.),
-                            Vec2::new(1., 1.),
-                            ent_t.translation(),
-                            if let Some(ent_size) = ent_sprite.custom_size {
-                                ent_size
-                            } else {
-                                continue;
-                            },
-                        )
-                        .is_some()
-                        {
-                            if keys.pressed(KeyCode::ShiftLeft) {
-                                selected_list.entities.push(ent);
-                                info!("select : {:?}", ent);
-                            } else {
-                                selected_list.entities = vec![ent];
-                                info!("select : {:?}", ent);
-                            }
-                            // entity_select = true;
+                    for ent in units_at(&rapier_context, &hitboxes, mouse_pos) {
+                        if keys.pressed(KeyCode::ShiftLeft) {
+                            selected_list.entities.push(ent);
+                            info!("select : {:?}", ent);
+                        } else {
+                            selected_list.entities = vec![ent];
+                            info!("select : {:?}", ent);
                         }
+                        // entity_select = true;
                     }
                     // if !entity_select {
                     //     selected_list.entities.clear();
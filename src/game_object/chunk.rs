@@ -0,0 +1,445 @@
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::{AssetServer, Assets, Handle, LoadState},
+    ecs::{
+        change_detection::DetectChanges,
+        component::Component,
+        entity::Entity,
+        query::{Or, With, Without},
+        removal_detection::RemovedComponents,
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    math::{IVec2, Rect, UVec2, Vec2},
+    prelude::default,
+    render::{
+        camera::{Camera, OrthographicProjection},
+        color::Color,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::Image,
+    },
+    sprite::{Sprite, SpriteBundle},
+    transform::components::Transform,
+    utils::{HashMap, HashSet},
+};
+
+use crate::AppState;
+
+use super::{battle::Stat, player::Player, render_layer::RenderLayer, spawner::Spawner, Target};
+
+pub struct ChunkPlugin;
+
+impl Plugin for ChunkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkedTiles>()
+            .init_resource::<ChunkMap>()
+            .add_systems(
+                Update,
+                (
+                    track_chunk_members,
+                    update_loaded_chunks,
+                    freeze_dormant,
+                    bake_chunks,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// side of a chunk in world units, a baked chunk having one pixel per unit.
+pub const CHUNK_WORLD_SIZE: f32 = 512.;
+/// chunks loaded around every player, so fights out of sight keep going.
+const PLAYER_CHUNK_RADIUS: i32 = 1;
+/// chunk textures baked in one frame at most, the rest waiting for the next frames.
+const BAKES_PER_FRAME: usize = 2;
+
+/// what a tile looks like: a part of an image, or a plain color without one.
+#[derive(Debug, Clone)]
+pub struct TileSprite {
+    pub image: Option<Handle<Image>>,
+    /// pixels of the image to draw, the whole image when `None`.
+    pub rect: Option<Rect>,
+    /// multiplied with the image, or the tile's color without one.
+    pub color: Color,
+}
+
+impl TileSprite {
+    pub fn color(color: Color) -> Self {
+        Self {
+            image: None,
+            rect: None,
+            color,
+        }
+    }
+}
+
+/// grid of tiles with its top left corner at `origin`, from a map file or the world generator.
+#[derive(Debug, Clone)]
+pub struct TileLayer {
+    pub origin: Vec2,
+    pub tile_size: Vec2,
+    pub width: u32,
+    pub height: u32,
+    /// tiles row by row from the top, `None` where there's nothing to draw.
+    pub tiles: Vec<Option<TileSprite>>,
+    /// layers are drawn on top of each other from the lowest `z`.
+    pub z: f32,
+}
+
+impl TileLayer {
+    fn rect(&self) -> Rect {
+        let size = self.tile_size * Vec2::new(self.width as f32, self.height as f32);
+        Rect::new(
+            self.origin.x,
+            self.origin.y - size.y,
+            self.origin.x + size.x,
+            self.origin.y,
+        )
+    }
+}
+
+/**
+ Every tile layer of the world, drawn by chunk.
+
+ chunks aren't made of a sprite per tile: the tiles of every layer under a chunk are baked in one texture
+ when the chunk gets loaded, and thrown away when it's unloaded.
+*/
+#[derive(Resource, Default)]
+pub struct ChunkedTiles {
+    layers: Vec<TileLayer>,
+}
+
+impl ChunkedTiles {
+    pub fn add_layer(&mut self, layer: TileLayer) {
+        self.layers.push(layer);
+        self.layers.sort_by(|a, b| a.z.total_cmp(&b.z));
+    }
+
+    /// top left corner and tile size of the lowest layer, the grid buildings are placed on.
+    pub fn grid(&self) -> Option<(Vec2, Vec2)> {
+        self.layers
+            .first()
+            .map(|layer| (layer.origin, layer.tile_size))
+    }
+
+    fn images(&self) -> impl Iterator<Item = &Handle<Image>> {
+        self.layers
+            .iter()
+            .flat_map(|layer| layer.tiles.iter().flatten())
+            .filter_map(|tile| tile.image.as_ref())
+    }
+}
+
+/// chunk the entity stands in, kept up to date for units and spawners.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkCoord(pub IVec2);
+
+/// put on monsters and spawners out of the loaded chunks, which stop thinking until they're loaded again.
+#[derive(Component)]
+pub struct Dormant;
+
+/// baked texture of the tiles of a chunk.
+#[derive(Component)]
+pub struct TileChunk {
+    pub coord: IVec2,
+}
+
+/// which chunks are loaded and what's in every chunk.
+#[derive(Resource, Default)]
+pub struct ChunkMap {
+    loaded: HashSet<IVec2>,
+    members: HashMap<IVec2, HashSet<Entity>>,
+}
+
+impl ChunkMap {
+    pub fn chunk_of(position: Vec2) -> IVec2 {
+        (position / CHUNK_WORLD_SIZE).floor().as_ivec2()
+    }
+
+    pub fn chunk_rect(coord: IVec2) -> Rect {
+        let min = coord.as_vec2() * CHUNK_WORLD_SIZE;
+        Rect::from_corners(min, min + CHUNK_WORLD_SIZE)
+    }
+
+    pub fn is_loaded(&self, coord: IVec2) -> bool {
+        self.loaded.contains(&coord)
+    }
+
+    pub fn loaded(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.loaded.iter().copied()
+    }
+
+    /// units and spawners standing in the chunk.
+    pub fn members(&self, coord: IVec2) -> impl Iterator<Item = Entity> + '_ {
+        self.members.get(&coord).into_iter().flatten().copied()
+    }
+}
+
+/// moves units and spawners to the chunk they stand in.
+pub fn track_chunk_members(
+    mut commands: Commands,
+    mut chunks: ResMut<ChunkMap>,
+    mut entities: Query<
+        (Entity, &Transform, Option<&mut ChunkCoord>),
+        Or<(With<Stat>, With<Spawner>)>,
+    >,
+    mut removed: RemovedComponents<ChunkCoord>,
+) {
+    for ent in removed.read() {
+        for members in chunks.members.values_mut() {
+            members.remove(&ent);
+        }
+    }
+    for (ent, t, coord) in &mut entities {
+        let current = ChunkMap::chunk_of(t.translation.truncate());
+        match coord {
+            Some(mut coord) if coord.0 != current => {
+                if let Some(members) = chunks.members.get_mut(&coord.0) {
+                    members.remove(&ent);
+                }
+                coord.0 = current;
+            }
+            Some(_) => continue,
+            None => {
+                commands.entity(ent).insert(ChunkCoord(current));
+            }
+        }
+        chunks.members.entry(current).or_default().insert(ent);
+    }
+    chunks.members.retain(|_, members| !members.is_empty());
+}
+
+/**
+ loads the chunks in sight of the camera and around the players.
+
+ a chunk is only unloaded once it's a chunk further than that, so walking along a border doesn't
+ load and unload it every frame.
+*/
+pub fn update_loaded_chunks(
+    mut chunks: ResMut<ChunkMap>,
+    camera: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+    players: Query<&Transform, With<Player>>,
+) {
+    let mut wanted: Vec<(IVec2, IVec2)> = Vec::new();
+    for (t, projection) in &camera {
+        let center = t.translation.truncate();
+        let half = projection.area.half_size();
+        wanted.push((
+            ChunkMap::chunk_of(center - half),
+            ChunkMap::chunk_of(center + half),
+        ));
+    }
+    for t in &players {
+        let coord = ChunkMap::chunk_of(t.translation.truncate());
+        wanted.push((coord - PLAYER_CHUNK_RADIUS, coord + PLAYER_CHUNK_RADIUS));
+    }
+
+    chunks.loaded.retain(|coord| {
+        wanted
+            .iter()
+            .any(|(min, max)| coord.cmpge(*min - 1).all() && coord.cmple(*max + 1).all())
+    });
+    for (min, max) in wanted {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                chunks.loaded.insert(IVec2::new(x, y));
+            }
+        }
+    }
+}
+
+/// monsters out of combat and spawners in unloaded chunks go dormant, and wake up with their chunk.
+pub fn freeze_dormant(
+    mut commands: Commands,
+    chunks: Res<ChunkMap>,
+    entities: Query<(Option<&Target>, Option<&Dormant>), Without<Player>>,
+) {
+    for coord in chunks.members.keys() {
+        let loaded = chunks.is_loaded(*coord);
+        for ent in chunks.members(*coord) {
+            let Ok((target, dormant)) = entities.get(ent) else {
+                continue;
+            };
+            let fighting = target.is_some_and(|target| target.0.is_some());
+            match (!loaded && !fighting, dormant.is_some()) {
+                (true, false) => {
+                    commands.entity(ent).insert(Dormant);
+                }
+                (false, true) => {
+                    commands.entity(ent).remove::<Dormant>();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// bakes the tiles of newly loaded chunks and drops the textures of unloaded ones.
+pub fn bake_chunks(
+    mut commands: Commands,
+    tiles: Res<ChunkedTiles>,
+    chunks: Res<ChunkMap>,
+    server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    baked: Query<(Entity, &TileChunk)>,
+) {
+    let mut done = HashSet::new();
+    for (ent, chunk) in &baked {
+        // new layers are baked in again from scratch.
+        if tiles.is_changed() || !chunks.is_loaded(chunk.coord) {
+            commands.entity(ent).despawn();
+        } else {
+            done.insert(chunk.coord);
+        }
+    }
+
+    // tile images still loading would leave holes in the texture.
+    let waiting = tiles.images().any(|image| {
+        images.get(image).is_none() && server.get_load_state(image) != Some(LoadState::Failed)
+    });
+    if waiting {
+        return;
+    }
+
+    let mut todo: Vec<IVec2> = chunks
+        .loaded()
+        .filter(|coord| !done.contains(coord))
+        .filter(|coord| {
+            let rect = ChunkMap::chunk_rect(*coord);
+            tiles
+                .layers
+                .iter()
+                .any(|layer| !layer.rect().intersect(rect).is_empty())
+        })
+        .collect();
+    // the same order every frame, closest to the origin first.
+    todo.sort_by_key(|coord| (coord.length_squared(), coord.x, coord.y));
+
+    for coord in todo.into_iter().take(BAKES_PER_FRAME) {
+        let texture = bake_chunk(&tiles, &images, coord);
+        let rect = ChunkMap::chunk_rect(coord);
+        commands.spawn((
+            TileChunk { coord },
+            SpriteBundle {
+                texture: images.add(texture),
+                sprite: Sprite {
+                    custom_size: Some(rect.size()),
+                    ..default()
+                },
+                transform: Transform::from_translation(
+                    rect.center().extend(RenderLayer::Ground.z()),
+                ),
+                ..default()
+            },
+        ));
+    }
+}
+
+/// draws every tile over the chunk into one texture, a pixel per world unit.
+fn bake_chunk(tiles: &ChunkedTiles, images: &Assets<Image>, coord: IVec2) -> Image {
+    let size = CHUNK_WORLD_SIZE as u32;
+    let mut texture = Image::new_fill(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    let chunk = ChunkMap::chunk_rect(coord);
+
+    for layer in &tiles.layers {
+        let area = layer.rect().intersect(chunk);
+        if area.is_empty() {
+            continue;
+        }
+        // tiles under the chunk, rows counted from the top.
+        let first = UVec2::new(
+            ((area.min.x - layer.origin.x) / layer.tile_size.x).floor() as u32,
+            ((layer.origin.y - area.max.y) / layer.tile_size.y).floor() as u32,
+        );
+        let last = UVec2::new(
+            ((area.max.x - layer.origin.x) / layer.tile_size.x).ceil() as u32,
+            ((layer.origin.y - area.min.y) / layer.tile_size.y).ceil() as u32,
+        )
+        .min(UVec2::new(layer.width, layer.height));
+
+        for y in first.y..last.y {
+            for x in first.x..last.x {
+                let Some(tile) = &layer.tiles[(y * layer.width + x) as usize] else {
+                    continue;
+                };
+                let min = Vec2::new(
+                    layer.origin.x + x as f32 * layer.tile_size.x,
+                    layer.origin.y - (y + 1) as f32 * layer.tile_size.y,
+                );
+                let tile_rect = Rect::from_corners(min, min + layer.tile_size);
+                draw_tile(&mut texture, chunk, tile_rect, tile, images);
+            }
+        }
+    }
+    texture
+}
+
+fn draw_tile(
+    texture: &mut Image,
+    chunk: Rect,
+    tile_rect: Rect,
+    tile: &TileSprite,
+    images: &Assets<Image>,
+) {
+    let size = CHUNK_WORLD_SIZE as usize;
+    let tint = tile.color.as_rgba_f32();
+    let source = tile
+        .image
+        .as_ref()
+        .and_then(|image| images.get(image))
+        .filter(|image| {
+            matches!(
+                image.texture_descriptor.format,
+                TextureFormat::Rgba8UnormSrgb | TextureFormat::Rgba8Unorm
+            )
+        });
+    let area = tile_rect.intersect(chunk);
+
+    // pixels of the chunk texture, rows from the top.
+    let left = (area.min.x - chunk.min.x).round() as usize;
+    let right = ((area.max.x - chunk.min.x).round() as usize).min(size);
+    let top = (chunk.max.y - area.max.y).round() as usize;
+    let bottom = ((chunk.max.y - area.min.y).round() as usize).min(size);
+
+    for py in top..bottom {
+        for px in left..right {
+            let world = Vec2::new(chunk.min.x + px as f32 + 0.5, chunk.max.y - py as f32 - 0.5);
+            let mut color = tint;
+            if let Some(source) = source {
+                let image_size = source.size_f32();
+                let rect = tile
+                    .rect
+                    .unwrap_or(Rect::from_corners(Vec2::ZERO, image_size));
+                let u = (world.x - tile_rect.min.x) / tile_rect.width();
+                let v = (tile_rect.max.y - world.y) / tile_rect.height();
+                let sx = (rect.min.x + u * rect.width()).clamp(0., image_size.x - 1.) as usize;
+                let sy = (rect.min.y + v * rect.height()).clamp(0., image_size.y - 1.) as usize;
+                let i = (sy * image_size.x as usize + sx) * 4;
+                for (c, byte) in color.iter_mut().zip(&source.data[i..i + 4]) {
+                    *c *= *byte as f32 / 255.;
+                }
+            }
+
+            // blended over the layers below.
+            let i = (py * size + px) * 4;
+            let below = &mut texture.data[i..i + 4];
+            let alpha = color[3];
+            for c in 0..3 {
+                let under = below[c] as f32 / 255.;
+                below[c] = ((color[c] * alpha + under * (1. - alpha)) * 255.) as u8;
+            }
+            let under = below[3] as f32 / 255.;
+            below[3] = ((alpha + under * (1. - alpha)) * 255.) as u8;
+        }
+    }
+}
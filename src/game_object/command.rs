@@ -1,35 +1,49 @@
 use bevy::{
     app::{App, Plugin, Update},
+    asset::Assets,
     ecs::{
         component::Component,
         entity::Entity,
         event::EventReader,
         query::{Or, With, Without},
         schedule::{common_conditions::in_state, IntoSystemConfigs},
-        system::{Query, ResMut},
+        system::{Query, Res, ResMut},
     },
+    hierarchy::Parent,
     input::{
+        keyboard::KeyCode,
         mouse::{MouseButton, MouseButtonInput},
-        ButtonState,
+        ButtonState, Input,
     },
     log::info,
-    math::{Vec2, Vec3},
-    sprite::{collide_aabb::collide, Sprite},
-    transform::components::{GlobalTransform, Transform},
+    math::Vec3,
+    transform::components::GlobalTransform,
     window::{PrimaryWindow, Window},
     winit::{self, WinitWindows},
 };
+use bevy_rapier2d::plugin::RapierContext;
 use bevy_render::camera::Camera;
 
 use crate::{resources::resource::SelectedList, states::ActionState, AppState};
 
-use super::{monster::Monster, player::Player, MoveTarget};
+use super::{
+    build_mode::BuildMode,
+    monster::Monster,
+    physics::{units_at, Hitbox},
+    player::{Class, Player},
+    skill::{SkillBook, SkillCode, SkillInfo},
+    skill_definition::SkillDefinition,
+    MoveTarget,
+};
 
 pub struct CommandPlugin;
 
 impl Plugin for CommandPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, order_move.run_if(in_state(AppState::InGame)));
+        app.add_systems(
+            Update,
+            (order_move, order_skill).run_if(in_state(AppState::InGame)),
+        );
     }
 }
 
@@ -45,17 +59,19 @@ pub fn order_move(
         (With<Camera>, Without<Player>, Without<Monster>),
     >,
 
+    rapier_context: Res<RapierContext>,
+    hitboxes: Query<&Parent, With<Hitbox>>,
     mut entities: Query<
-        (
-            Entity,
-            &Transform,
-            &Sprite,
-            &mut MoveTarget,
-            &mut ActionState,
-        ),
-        (Or<(With<Player>, With<Monster>)>),
+        (Entity, &mut MoveTarget, &mut ActionState),
+        Or<(With<Player>, With<Monster>)>,
     >,
+    build_mode: Res<BuildMode>,
 ) {
+    // clicks place buildings in build mode.
+    if build_mode.is_active() {
+        mouse_event.clear();
+        return;
+    }
     let (cam, cam_t) = cam_q.single();
 
     let Some(mouse_pos) = window_q
@@ -70,17 +86,11 @@ pub fn order_move(
         match ev.state {
             ButtonState::Released => match ev.button {
                 MouseButton::Left => {
-                    for (ent, t, sprite, mut mv_targ, mut a_state) in &mut entities {
-                        if collide(
-                            Vec3::from((mouse_pos, 0.)),
-                            Vec2::new(1., 1.),
-                            t.translation,
-                            sprite.custom_size.unwrap(),
-                        )
-                        .is_some()
-                        {
-                            return;
-                        }
+                    // clicking a unit selects it instead.
+                    if !units_at(&rapier_context, &hitboxes, mouse_pos).is_empty() {
+                        return;
+                    }
+                    for (ent, mut mv_targ, mut a_state) in &mut entities {
                         if selected_list.entities.contains(&ent) {
                             let vec = Vec3::from((mouse_pos, 0.));
                             *mv_targ = MoveTarget(Some(vec));
@@ -94,3 +104,27 @@ pub fn order_move(
         }
     }
 }
+
+/// queues a class skill on the selected players with the number keys.
+pub fn order_skill(
+    keys: Res<Input<KeyCode>>,
+    selected_list: Res<SelectedList>,
+    skill_book: Res<SkillBook>,
+    definitions: Res<Assets<SkillDefinition>>,
+    mut players: Query<(Entity, &Player, &Class, &mut SkillInfo)>,
+) {
+    if !keys.just_pressed(KeyCode::Key1) {
+        return;
+    }
+    for (ent, player, class, mut skill_info) in &mut players {
+        if !selected_list.entities.contains(&ent) {
+            continue;
+        }
+        if let Class::MAGE = class {
+            skill_info.queue_skill(SkillCode::FireBall);
+            if let Some(definition) = skill_book.get(&SkillCode::FireBall, &definitions) {
+                info!("{} queues {}", player.name(), definition.name);
+            }
+        }
+    }
+}
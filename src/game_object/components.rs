@@ -21,10 +21,14 @@ use crate::states::{ActionState, BattleState};
 
 use super::{
     battle::{Damage, Stat},
+    buff::Buffs,
+    building::Building,
     monster::Monster,
+    physics::UnitPhysicsBundle,
     player::Player,
+    render_layer::{RenderLayer, YSort},
     skill::SkillInfo,
-    MoveTarget, Target,
+    Facing, MoveTarget, Target,
 };
 
 #[derive(Bundle)]
@@ -37,9 +41,13 @@ pub struct GameObjectBundle {
     pub battle_state: BattleState,
     pub skill_info: SkillInfo,
     pub team: Team,
+    pub buffs: Buffs,
+    pub facing: Facing,
+    pub physics: UnitPhysicsBundle,
+    pub y_sort: YSort,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum TeamType {
     PLAYER,
     MONSTER,
@@ -85,6 +93,10 @@ pub struct CastingBar {
     pub current: f32,
     pub max: f32,
     pub visibility: bool,
+    /// runs while the bar shows a broken cast.
+    pub interrupted: Timer,
+    /// the "Interrupted" text shown over the bar.
+    pub label: Option<Entity>,
 }
 
 #[derive(Bundle)]
@@ -93,6 +105,25 @@ pub struct CastingBarBundle {
     pub casting_bar: CastingBar,
 }
 
+#[derive(PartialEq)]
+pub enum PowerBarType {
+    FRONT,
+    BACKGROUND,
+}
+
+#[derive(Component)]
+pub struct PowerBar {
+    pub bar_type: PowerBarType,
+    pub target: Option<Entity>,
+    pub width: f32,
+}
+
+#[derive(Bundle)]
+pub struct PowerBarBundle {
+    pub sprite: SpriteBundle,
+    pub power_bar: PowerBar,
+}
+
 #[derive(Component)]
 pub struct DamagePopup {
     value: f32,
@@ -112,7 +143,10 @@ pub struct DamagePopupBundle {
 pub fn spawn_damage_popup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    entities: Query<(Entity, &Transform, &Sprite), Or<(With<Player>, With<Monster>)>>,
+    entities: Query<
+        (Entity, &Transform, &Sprite),
+        Or<(With<Player>, With<Monster>, With<Building>)>,
+    >,
     mut events: EventReader<Damage>,
 ) {
     for e in events.read() {
@@ -143,7 +177,7 @@ pub fn spawn_damage_popup(
                         //     asset_server.load("Consolas.ttf"),
                         //     Color::YELLOW,
                         // ),
-                        transform: Transform::from_xyz(pos.x, pos.y, 3.),
+                        transform: Transform::from_xyz(pos.x, pos.y, RenderLayer::Popups.z()),
                         ..Default::default()
                     },
                 });
@@ -156,9 +190,14 @@ pub fn damage_popup_system(
     mut commands: Commands,
     mut popups: Query<
         (Entity, &mut Transform, &mut DamagePopup, &mut Text),
-        (With<DamagePopup>, Without<Player>, Without<Monster>),
+        (
+            With<DamagePopup>,
+            Without<Player>,
+            Without<Monster>,
+            Without<Building>,
+        ),
     >,
-    entities: Query<(Entity, &Transform), Or<(With<Player>, With<Monster>)>>,
+    entities: Query<(Entity, &Transform), Or<(With<Player>, With<Monster>, With<Building>)>>,
     time: Res<Time>,
 ) {
     let speed = 20.;
@@ -198,6 +237,3 @@ pub fn damage_popup_system(
 
 #[derive(Component, PartialEq)]
 pub struct Team(pub TeamType);
-
-#[derive(Component)]
-pub struct Building {}
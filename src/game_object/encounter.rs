@@ -0,0 +1,571 @@
+use std::f32::consts::TAU;
+
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::{Asset, AssetApp, AssetPath, AssetServer, Assets, Handle},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{Event, EventReader, EventWriter},
+        query::{Has, With, Without},
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res},
+    },
+    log::info,
+    math::Vec3,
+    reflect::TypePath,
+    time::{Stopwatch, Time, Timer, TimerMode},
+    transform::components::Transform,
+};
+use rand::Rng;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{
+    data::RonLoader,
+    states::{ActionState, BattleState},
+    AppState,
+};
+
+use super::{
+    battle::{die, Dead, Died, Stat, UnitBars},
+    buff::{BuffKind, Buffs},
+    components::TeamType,
+    ground_effect::{GroundEffectDefinition, SpawnGroundEffect},
+    monster::{spawn_monster_by_id, Monster, MonsterTemplates, EVADE_TIMEOUT},
+    pet::Pet,
+    player::Player,
+    spawner::ToggleSpawner,
+    Target,
+};
+
+pub struct EncounterPlugin;
+
+impl Plugin for EncounterPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<EncounterDefinition>()
+            .register_asset_loader(RonLoader::<EncounterDefinition>::new(&["encounter.ron"]))
+            .add_event::<EncounterStarted>()
+            .add_event::<EncounterPhaseChanged>()
+            .add_event::<EncounterWiped>()
+            .add_event::<EncounterVictory>()
+            .add_systems(
+                Update,
+                (
+                    spawn_pending_encounters,
+                    start_encounters,
+                    run_encounters,
+                    wipe_encounters,
+                    win_encounters,
+                    announce_encounters,
+                )
+                    .chain()
+                    .after(die)
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/**
+ Data driven description of a boss fight, loaded from the `encounters` asset folder (`.encounter.ron`).
+
+ the fight goes through its `phases` in order, moving on as soon as the next phase's
+ [`PhaseTrigger`] is met, and every phase uses its own [`ScheduledAbility`]s.
+ after `enrage` seconds the boss gets [`BuffKind::Enraged`] until the fight is over.
+*/
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct EncounterDefinition {
+    pub name: String,
+    pub boss: BossDefinition,
+    #[serde(default)]
+    pub enrage: Option<f32>,
+    #[serde(deserialize_with = "at_least_one_phase")]
+    pub phases: Vec<PhaseDefinition>,
+}
+
+/// an encounter without phases has nothing to run, the file is rejected.
+fn at_least_one_phase<'de, D>(deserializer: D) -> Result<Vec<PhaseDefinition>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let phases = Vec::<PhaseDefinition>::deserialize(deserializer)?;
+    if phases.is_empty() {
+        return Err(de::Error::invalid_length(0, &"at least one phase"));
+    }
+    Ok(phases)
+}
+
+/// the monster template the boss is built from, see [`super::monster_template::MonsterTemplate`].
+#[derive(Deserialize, Debug, Clone)]
+pub struct BossDefinition {
+    pub monster: String,
+    pub level: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PhaseDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub trigger: PhaseTrigger,
+    #[serde(default)]
+    pub abilities: Vec<ScheduledAbility>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum PhaseTrigger {
+    /// active from the pull, only meaningful for the first phase.
+    #[default]
+    Start,
+    /// boss health ratio drops to the value, `0.5` for 50%.
+    HealthBelow(f32),
+    /// seconds since the pull.
+    After(f32),
+}
+
+/// an ability used `first` seconds into its phase, then every `every` seconds.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScheduledAbility {
+    pub ability: BossAbility,
+    #[serde(default)]
+    pub first: Option<f32>,
+    pub every: f32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum BossAbility {
+    /// leaves a zone on the ground under the chosen unit.
+    GroundEffect {
+        target: AbilityTarget,
+        effect: GroundEffectDefinition,
+    },
+    /// calls `count` monsters of the template around the boss, attacking the boss's target.
+    SpawnAdds {
+        monster: String,
+        count: u32,
+        radius: f32,
+    },
+    /// [`BuffKind::Sundered`] on the boss's target, so another tank has to take over.
+    TankSwap { duration: f32 },
+    /// turns the spawners with the given name on or off.
+    ToggleSpawner { name: String, active: bool },
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum AbilityTarget {
+    Boss,
+    Target,
+    RandomPlayer,
+}
+
+/**
+ Runtime state of a boss fight, on the boss.
+
+ the fight starts when the boss picks a target,
+ and resets when every player who joined it is dead.
+*/
+#[derive(Component)]
+pub struct Encounter {
+    definition: Handle<EncounterDefinition>,
+    engaged: bool,
+    phase: usize,
+    elapsed: Stopwatch,
+    abilities: Vec<Timer>,
+    enraged: bool,
+    /// players who fought the boss since the pull, pets left out.
+    roster: Vec<Entity>,
+}
+
+impl Encounter {
+    fn new(definition: Handle<EncounterDefinition>) -> Self {
+        Self {
+            definition,
+            engaged: false,
+            phase: 0,
+            elapsed: Stopwatch::new(),
+            abilities: Vec::new(),
+            enraged: false,
+            roster: Vec::new(),
+        }
+    }
+
+    /// starts the abilities of `phase`, `false` when the definition has no such phase.
+    fn enter_phase(&mut self, phase: usize, definition: &EncounterDefinition) -> bool {
+        let Some(next) = definition.phases.get(phase) else {
+            return false;
+        };
+        self.phase = phase;
+        self.abilities = next
+            .abilities
+            .iter()
+            .map(|scheduled| {
+                Timer::from_seconds(scheduled.first.unwrap_or(scheduled.every), TimerMode::Once)
+            })
+            .collect();
+        true
+    }
+
+    fn reset(&mut self) {
+        self.engaged = false;
+        self.phase = 0;
+        self.elapsed.reset();
+        self.abilities.clear();
+        self.enraged = false;
+        self.roster.clear();
+    }
+}
+
+/// monster called in by an encounter, gone when the encounter is over.
+#[derive(Component)]
+pub struct EncounterAdd {
+    pub boss: Entity,
+}
+
+/// boss waiting for its definition to load, see [`spawn_encounter`].
+#[derive(Component)]
+pub struct PendingEncounter {
+    definition: Handle<EncounterDefinition>,
+    transform: Transform,
+}
+
+#[derive(Event)]
+pub struct EncounterStarted {
+    pub boss: Entity,
+}
+
+#[derive(Event)]
+pub struct EncounterPhaseChanged {
+    pub boss: Entity,
+    pub phase: usize,
+}
+
+#[derive(Event)]
+pub struct EncounterWiped {
+    pub boss: Entity,
+}
+
+#[derive(Event)]
+pub struct EncounterVictory {
+    pub boss: Entity,
+}
+
+/// places the boss of the encounter file at `path` once the file is loaded.
+pub fn spawn_encounter(
+    commands: &mut Commands,
+    server: &AssetServer,
+    path: impl Into<AssetPath<'static>>,
+    transform: Transform,
+) {
+    commands.spawn(PendingEncounter {
+        definition: server.load(path),
+        transform,
+    });
+}
+
+pub fn spawn_pending_encounters(
+    mut commands: Commands,
+    definitions: Res<Assets<EncounterDefinition>>,
+    mut templates: MonsterTemplates,
+    pending: Query<(Entity, &PendingEncounter)>,
+) {
+    for (ent, pending) in &pending {
+        let Some(definition) = definitions.get(&pending.definition) else {
+            continue;
+        };
+        let Some(id) = spawn_monster_by_id(
+            &mut commands,
+            &mut templates,
+            &definition.boss.monster,
+            definition.boss.level,
+            pending.transform,
+        ) else {
+            continue;
+        };
+        commands
+            .entity(id)
+            .insert(Encounter::new(pending.definition.clone()));
+        commands.entity(ent).despawn();
+    }
+}
+
+/// pulls the encounter as soon as its boss has a target.
+pub fn start_encounters(
+    definitions: Res<Assets<EncounterDefinition>>,
+    mut bosses: Query<(Entity, &Target, &Buffs, &mut Encounter)>,
+    mut started_evt: EventWriter<EncounterStarted>,
+) {
+    for (ent, target, buffs, mut encounter) in &mut bosses {
+        if encounter.engaged || target.0.is_none() || buffs.has(BuffKind::Evading) {
+            continue;
+        }
+        let Some(definition) = definitions.get(&encounter.definition) else {
+            continue;
+        };
+        if !encounter.enter_phase(0, definition) {
+            continue;
+        }
+        encounter.engaged = true;
+        encounter.elapsed.reset();
+        started_evt.send(EncounterStarted { boss: ent });
+    }
+}
+
+/// moves engaged encounters through their phases and uses their abilities when they're due.
+pub fn run_encounters(
+    mut commands: Commands,
+    time: Res<Time>,
+    definitions: Res<Assets<EncounterDefinition>>,
+    mut bosses: Query<(
+        Entity,
+        &Transform,
+        &Stat,
+        &Target,
+        &mut Buffs,
+        &mut Encounter,
+    )>,
+    mut players: Query<
+        (Entity, &Transform, &mut Buffs),
+        (With<Player>, Without<Dead>, Without<Encounter>),
+    >,
+    mut phase_evt: EventWriter<EncounterPhaseChanged>,
+    mut ground_effect_evt: EventWriter<SpawnGroundEffect>,
+    mut templates: MonsterTemplates,
+    mut spawner_evt: EventWriter<ToggleSpawner>,
+) {
+    for (ent, t, stat, target, mut buffs, mut encounter) in &mut bosses {
+        if !encounter.engaged {
+            continue;
+        }
+        let Some(definition) = definitions.get(&encounter.definition) else {
+            continue;
+        };
+        encounter.elapsed.tick(time.delta());
+        let elapsed = encounter.elapsed.elapsed_secs();
+
+        if !encounter.enraged && definition.enrage.is_some_and(|after| elapsed >= after) {
+            encounter.enraged = true;
+            buffs.add_permanent(BuffKind::Enraged, ent);
+        }
+
+        while let Some(next) = definition.phases.get(encounter.phase + 1) {
+            let reached = match next.trigger {
+                PhaseTrigger::Start => true,
+                PhaseTrigger::HealthBelow(ratio) => stat.hp.ratio() <= ratio,
+                PhaseTrigger::After(secs) => elapsed >= secs,
+            };
+            if !reached {
+                break;
+            }
+            let phase = encounter.phase + 1;
+            encounter.enter_phase(phase, definition);
+            phase_evt.send(EncounterPhaseChanged { boss: ent, phase });
+        }
+
+        // the phase may be gone from a reloaded definition, the fight starts over.
+        let Some(phase) = definition.phases.get(encounter.phase) else {
+            encounter.reset();
+            buffs.remove(BuffKind::Enraged);
+            continue;
+        };
+        for (scheduled, timer) in phase.abilities.iter().zip(encounter.abilities.iter_mut()) {
+            timer.tick(time.delta());
+            if !timer.finished() {
+                continue;
+            }
+            *timer = Timer::from_seconds(scheduled.every, TimerMode::Once);
+
+            match &scheduled.ability {
+                BossAbility::GroundEffect { target: at, effect } => {
+                    let position = match at {
+                        AbilityTarget::Boss => Some(t.translation),
+                        AbilityTarget::Target => target
+                            .0
+                            .and_then(|targ| players.get(targ).ok())
+                            .map(|(_, targ_t, _)| targ_t.translation),
+                        AbilityTarget::RandomPlayer => {
+                            let positions: Vec<Vec3> =
+                                players.iter().map(|(_, t, _)| t.translation).collect();
+                            (!positions.is_empty()).then(|| {
+                                positions[rand::thread_rng().gen_range(0..positions.len())]
+                            })
+                        }
+                    };
+                    if let Some(position) = position {
+                        ground_effect_evt.send(SpawnGroundEffect {
+                            definition: effect.clone(),
+                            owner: ent,
+                            team: TeamType::MONSTER,
+                            level: stat.level,
+                            position,
+                        });
+                    }
+                }
+                BossAbility::SpawnAdds {
+                    monster,
+                    count,
+                    radius,
+                } => {
+                    for i in 0..*count {
+                        let angle = TAU * i as f32 / *count as f32;
+                        let offset = Vec3::new(angle.cos(), angle.sin(), 0.) * *radius;
+                        let Some(id) = spawn_monster_by_id(
+                            &mut commands,
+                            &mut templates,
+                            monster,
+                            stat.level,
+                            Transform::from_translation(t.translation + offset),
+                        ) else {
+                            break;
+                        };
+                        commands.entity(id).insert((
+                            EncounterAdd { boss: ent },
+                            Target(target.0),
+                            ActionState::BATTLE,
+                        ));
+                    }
+                }
+                BossAbility::TankSwap { duration } => {
+                    if let Some(Ok((_, _, mut targ_buffs))) =
+                        target.0.map(|targ| players.get_mut(targ))
+                    {
+                        targ_buffs.add(BuffKind::Sundered, ent, *duration);
+                    }
+                }
+                BossAbility::ToggleSpawner { name, active } => {
+                    spawner_evt.send(ToggleSpawner {
+                        name: name.clone(),
+                        active: *active,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/**
+ resets engaged encounters once every player of their roster is dead,
+ or when the boss is pulled off its leash.
+
+ the roster is made of the boss's targets and of the players targeting it, pets left out.
+ the boss drops its enrage and evades back to its spawn point, where it heals up,
+ and the adds it called are removed.
+*/
+pub fn wipe_encounters(
+    mut commands: Commands,
+    players: Query<(Entity, &Target, Has<Dead>), (With<Player>, Without<Pet>)>,
+    mut bosses: Query<
+        (
+            Entity,
+            &mut Target,
+            &mut ActionState,
+            &mut BattleState,
+            &mut Buffs,
+            &mut Encounter,
+        ),
+        (With<Monster>, Without<Player>),
+    >,
+    adds: Query<(Entity, &EncounterAdd)>,
+    bars: UnitBars,
+    mut wiped_evt: EventWriter<EncounterWiped>,
+) {
+    for (ent, mut target, mut a_state, mut b_state, mut buffs, mut encounter) in &mut bosses {
+        if !encounter.engaged {
+            continue;
+        }
+        for (player, player_target, dead) in &players {
+            let joined = !dead && (target.0 == Some(player) || player_target.0 == Some(ent));
+            if joined && !encounter.roster.contains(&player) {
+                encounter.roster.push(player);
+            }
+        }
+        // a player gone from the world counts as dead.
+        let all_dead = !encounter.roster.is_empty()
+            && encounter
+                .roster
+                .iter()
+                .all(|player| players.get(*player).map_or(true, |(_, _, dead)| dead));
+        if !(all_dead || buffs.has(BuffKind::Evading)) {
+            continue;
+        }
+        encounter.reset();
+        buffs.remove(BuffKind::Enraged);
+        if !buffs.has(BuffKind::Evading) {
+            buffs.add(BuffKind::Evading, ent, EVADE_TIMEOUT);
+        }
+        *target = Target(None);
+        *a_state = ActionState::IDLE;
+        *b_state = BattleState::EVADING;
+        dismiss_adds(&mut commands, &adds, &bars, ent);
+        wiped_evt.send(EncounterWiped { boss: ent });
+    }
+}
+
+/// takes the adds of the boss away with their bars, without a [`Died`] so they leave no reward.
+fn dismiss_adds(
+    commands: &mut Commands,
+    adds: &Query<(Entity, &EncounterAdd)>,
+    bars: &UnitBars,
+    boss: Entity,
+) {
+    for (ent, add) in adds {
+        if add.boss == boss {
+            bars.despawn_unit(commands, ent);
+        }
+    }
+}
+
+/// a boss killed wins its encounter, its adds go with it.
+pub fn win_encounters(
+    mut commands: Commands,
+    mut died_evt: EventReader<Died>,
+    bosses: Query<(), With<Encounter>>,
+    adds: Query<(Entity, &EncounterAdd)>,
+    bars: UnitBars,
+    mut victory_evt: EventWriter<EncounterVictory>,
+) {
+    for died in died_evt.read() {
+        if !bosses.contains(died.entity) {
+            continue;
+        }
+        dismiss_adds(&mut commands, &adds, &bars, died.entity);
+        victory_evt.send(EncounterVictory { boss: died.entity });
+    }
+}
+
+/// logs the progress of every encounter.
+pub fn announce_encounters(
+    definitions: Res<Assets<EncounterDefinition>>,
+    bosses: Query<&Encounter>,
+    mut started_evt: EventReader<EncounterStarted>,
+    mut phase_evt: EventReader<EncounterPhaseChanged>,
+    mut wiped_evt: EventReader<EncounterWiped>,
+    mut victory_evt: EventReader<EncounterVictory>,
+) {
+    let definition = |boss: Entity| {
+        bosses
+            .get(boss)
+            .ok()
+            .and_then(|encounter| definitions.get(&encounter.definition))
+    };
+    for evt in started_evt.read() {
+        if let Some(definition) = definition(evt.boss) {
+            info!("{} engaged.", definition.name);
+        }
+    }
+    for evt in phase_evt.read() {
+        let Some(definition) = definition(evt.boss) else {
+            continue;
+        };
+        if let Some(phase) = definition.phases.get(evt.phase) {
+            info!("{} enters {}.", definition.name, phase.name);
+        }
+    }
+    for evt in wiped_evt.read() {
+        if let Some(definition) = definition(evt.boss) {
+            info!("{} reset, the raid wiped.", definition.name);
+        }
+    }
+    for evt in victory_evt.read() {
+        if let Some(definition) = definition(evt.boss) {
+            info!("{} defeated.", definition.name);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::{collections::HashMap, f32::consts::TAU};
+
+use bevy::{
+    app::{App, Plugin, Startup, Update},
+    asset::{Asset, AssetApp, AssetServer, Assets, Handle},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res, ResMut, Resource},
+    },
+    hierarchy::BuildChildren,
+    log::warn,
+    math::{Quat, Vec2},
+    prelude::{default, SpatialBundle},
+    reflect::TypePath,
+    sprite::{Anchor, Sprite, SpriteBundle, SpriteSheetBundle, TextureAtlas, TextureAtlasSprite},
+    time::{Time, Timer, TimerMode},
+    transform::components::Transform,
+};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{data::RonLoader, AppState};
+
+use super::{
+    frame_atlas::FrameAtlases,
+    physics::ObstacleBundle,
+    render_layer::YSort,
+    tree::{AnimationIndices, AnimationTimer},
+};
+
+pub struct EnvironmentPlugin;
+
+impl Plugin for EnvironmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<EnvironmentCatalog>()
+            .register_asset_loader(RonLoader::<EnvironmentCatalog>::new(&["catalog.ron"]))
+            .init_resource::<EnvironmentBook>()
+            .add_systems(Startup, load_environment_book)
+            .add_systems(
+                Update,
+                (spawn_pending_objects, sway_in_wind).run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/**
+ Every [`EnvironmentObject`] by id, loaded from `environment/objects.catalog.ron`.
+
+ map files and the world generator place objects by their id in this catalog.
+*/
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct EnvironmentCatalog(pub HashMap<String, EnvironmentObject>);
+
+/// a kind of decoration of the world, like a tree or a rock.
+#[derive(Deserialize, Debug, Clone)]
+pub struct EnvironmentObject {
+    pub sprite: EnvironmentSprite,
+    /// drawn size, the bottom middle of the sprite standing on the object's position.
+    pub size: (f32, f32),
+    /// what blocks movement, `None` to walk through the object.
+    #[serde(default)]
+    pub footprint: Option<Footprint>,
+    #[serde(default)]
+    pub sway: Option<Sway>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum EnvironmentSprite {
+    /// one of the images, picked at random for every instance.
+    Variants(Vec<String>),
+    /// looping animation over the `first` to `last` tiles of a sprite sheet, from a random frame.
+    Animated {
+        path: String,
+        tile_size: (f32, f32),
+        columns: usize,
+        rows: usize,
+        first: usize,
+        last: usize,
+        frame_time: f32,
+    },
+    /// looping animation over loose frame images, see [`FrameAtlases`] for the `source`.
+    Frames { source: String, frame_time: f32 },
+}
+
+/// blocking box of `size`, `offset` from the object's position, usually around a trunk.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Footprint {
+    pub size: (f32, f32),
+    #[serde(default)]
+    pub offset: (f32, f32),
+}
+
+/// leaning back and forth up to `angle` radians, a full swing every `period` seconds.
+#[derive(Component, Deserialize, Debug, Clone, Copy)]
+pub struct Sway {
+    pub angle: f32,
+    pub period: f32,
+    /// where in its swing the object starts, random so that a forest doesn't move as one.
+    #[serde(skip)]
+    pub phase: f32,
+}
+
+/// an object of the catalog standing in the world.
+#[derive(Component, Debug)]
+pub struct EnvironmentInstance;
+
+/// an object waiting for the catalog to be loaded.
+#[derive(Component)]
+pub struct PendingObject {
+    id: String,
+}
+
+/// Handle of the [`EnvironmentCatalog`] file.
+#[derive(Resource, Default)]
+pub struct EnvironmentBook {
+    catalog: Handle<EnvironmentCatalog>,
+}
+
+pub fn load_environment_book(mut book: ResMut<EnvironmentBook>, server: Res<AssetServer>) {
+    book.catalog = server.load("environment/objects.catalog.ron");
+}
+
+/// places the object `id` of the catalog, its bottom middle at `transform`.
+pub fn place_object(commands: &mut Commands, id: impl Into<String>, transform: Transform) {
+    commands.spawn((
+        PendingObject { id: id.into() },
+        SpatialBundle::from_transform(transform),
+    ));
+}
+
+pub fn spawn_pending_objects(
+    mut commands: Commands,
+    book: Res<EnvironmentBook>,
+    catalogs: Res<Assets<EnvironmentCatalog>>,
+    server: Res<AssetServer>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut frame_atlases: ResMut<FrameAtlases>,
+    pending: Query<(Entity, &PendingObject)>,
+) {
+    let Some(catalog) = catalogs.get(&book.catalog) else {
+        return;
+    };
+    let mut rng = rand::thread_rng();
+    for (ent, pending) in &pending {
+        let Some(object) = catalog.0.get(&pending.id) else {
+            warn!("no environment object {:?}", pending.id);
+            commands.entity(ent).despawn();
+            continue;
+        };
+        if matches!(&object.sprite, EnvironmentSprite::Variants(paths) if paths.is_empty()) {
+            warn!("environment object {:?} has no image", pending.id);
+            commands.entity(ent).despawn();
+            continue;
+        }
+        let frames = match &object.sprite {
+            EnvironmentSprite::Frames { source, .. } => {
+                let frames = frame_atlases.request(&server, source).cloned();
+                if frames.is_none() {
+                    // waits for the frames to be packed, unless they never will be.
+                    if frame_atlases.is_failed(source) {
+                        commands.entity(ent).despawn();
+                    }
+                    continue;
+                }
+                frames
+            }
+            _ => None,
+        };
+
+        let size = Vec2::new(object.size.0, object.size.1);
+        let mut entity = commands.entity(ent);
+        entity
+            .remove::<PendingObject>()
+            .insert((EnvironmentInstance, YSort::default()));
+        if let Some(footprint) = object.footprint {
+            entity.insert(ObstacleBundle::new(
+                Vec2::new(footprint.size.0, footprint.size.1),
+                Vec2::new(footprint.offset.0, footprint.offset.1),
+            ));
+        }
+
+        // the sprite is a child so that swaying doesn't tilt the footprint.
+        let sway = object.sway.map(|sway| Sway {
+            phase: rng.gen_range(0.0..TAU),
+            ..sway
+        });
+        entity.with_children(|parent| {
+            let mut child = match &object.sprite {
+                EnvironmentSprite::Variants(paths) => {
+                    let path = &paths[rng.gen_range(0..paths.len())];
+                    parent.spawn(SpriteBundle {
+                        texture: server.load(path),
+                        sprite: Sprite {
+                            custom_size: Some(size),
+                            anchor: Anchor::BottomCenter,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                }
+                EnvironmentSprite::Animated {
+                    path,
+                    tile_size,
+                    columns,
+                    rows,
+                    first,
+                    last,
+                    frame_time,
+                } => {
+                    let atlas = TextureAtlas::from_grid(
+                        server.load(path),
+                        Vec2::new(tile_size.0, tile_size.1),
+                        *columns,
+                        *rows,
+                        None,
+                        None,
+                    );
+                    let mut timer = Timer::from_seconds(*frame_time, TimerMode::Repeating);
+                    timer.set_elapsed(timer.duration().mul_f32(rng.gen()));
+                    parent.spawn((
+                        SpriteSheetBundle {
+                            texture_atlas: atlases.add(atlas),
+                            sprite: TextureAtlasSprite {
+                                index: rng.gen_range(*first..=*last),
+                                custom_size: Some(size),
+                                anchor: Anchor::BottomCenter,
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        AnimationIndices {
+                            first: *first,
+                            last: *last,
+                        },
+                        AnimationTimer(timer),
+                    ))
+                }
+                EnvironmentSprite::Frames { frame_time, .. } => {
+                    let frames = frames.as_ref().unwrap();
+                    let mut timer = Timer::from_seconds(*frame_time, TimerMode::Repeating);
+                    timer.set_elapsed(timer.duration().mul_f32(rng.gen()));
+                    parent.spawn((
+                        SpriteSheetBundle {
+                            texture_atlas: frames.atlas.clone(),
+                            sprite: TextureAtlasSprite {
+                                index: rng.gen_range(0..frames.len()),
+                                custom_size: Some(size),
+                                anchor: Anchor::BottomCenter,
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        AnimationIndices {
+                            first: 0,
+                            last: frames.len() - 1,
+                        },
+                        AnimationTimer(timer),
+                    ))
+                }
+            };
+            if let Some(sway) = sway {
+                child.insert(sway);
+            }
+        });
+    }
+}
+
+pub fn sway_in_wind(time: Res<Time>, mut objects: Query<(&Sway, &mut Transform)>) {
+    let t = time.elapsed_seconds();
+    for (sway, mut transform) in &mut objects {
+        let angle = sway.angle * (t * TAU / sway.period + sway.phase).sin();
+        transform.rotation = Quat::from_rotation_z(angle);
+    }
+}
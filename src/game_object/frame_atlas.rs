@@ -0,0 +1,306 @@
+use std::{any::TypeId, collections::HashMap};
+
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::{Asset, AssetApp, AssetServer, Assets, Handle, LoadState, LoadedFolder},
+    ecs::system::{Res, ResMut, Resource},
+    log::{info, warn},
+    math::Vec2,
+    reflect::TypePath,
+    render::texture::Image,
+    sprite::{TextureAtlas, TextureAtlasBuilder},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::data::RonLoader;
+
+pub struct FrameAtlasPlugin;
+
+impl Plugin for FrameAtlasPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<FrameManifest>()
+            .register_asset_loader(RonLoader::<FrameManifest>::new(&["frames.ron"]))
+            .init_resource::<FrameAtlases>()
+            .add_systems(Update, build_frame_atlases);
+    }
+}
+
+/**
+ The frames of a folder, written next to it as `<folder>.frames.ron`.
+
+ web builds can't list a folder, they read the frames from this file instead. native debug builds
+ write it every time they build an atlas from the folder, so it only needs to be committed.
+*/
+#[derive(Asset, TypePath, Serialize, Deserialize, Debug, PartialEq)]
+pub struct FrameManifest {
+    /// asset paths of every image of the folder.
+    pub frames: Vec<String>,
+}
+
+/**
+ where the frames of an atlas are, either a folder (`sprites/trees/anim_tree`) or the images of
+ a folder whose name matches a pattern with one `*` (`sprites/trees/anim_tree/frame_*.png`).
+*/
+#[derive(Debug, Clone)]
+struct FrameSource {
+    folder: String,
+    pattern: Option<(String, String)>,
+}
+
+impl FrameSource {
+    fn parse(source: &str) -> Self {
+        match source.rsplit_once('/') {
+            Some((folder, name)) if name.contains('*') => {
+                let (prefix, suffix) = name.split_once('*').unwrap();
+                Self {
+                    folder: folder.to_owned(),
+                    pattern: Some((prefix.to_owned(), suffix.to_owned())),
+                }
+            }
+            _ => Self {
+                folder: source.trim_end_matches('/').to_owned(),
+                pattern: None,
+            },
+        }
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        match &self.pattern {
+            Some((prefix, suffix)) => {
+                name.len() >= prefix.len() + suffix.len()
+                    && name.starts_with(prefix.as_str())
+                    && name.ends_with(suffix.as_str())
+            }
+            None => true,
+        }
+    }
+
+    fn manifest_path(&self) -> String {
+        format!("{}.frames.ron", self.folder)
+    }
+}
+
+/// the number at the end of a file name, `12` for `walk_0012.png`.
+fn numeric_suffix(path: &str) -> Option<u64> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let stem = name.split('.').next().unwrap_or(name);
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    stem[stem.len() - digits..].parse().ok()
+}
+
+/**
+ sort key of a frame, so that `frame_10` comes after `frame_9`.
+
+ frames without a number come first, frames with the same number go by name.
+*/
+fn frame_order(path: &str) -> (Option<u64>, String) {
+    (numeric_suffix(path), path.to_owned())
+}
+
+/// an atlas of loose frames, its texture `i` being the `i`th frame by numeric suffix.
+#[derive(Debug, Clone)]
+pub struct FrameAtlas {
+    pub atlas: Handle<TextureAtlas>,
+    /// asset path of every frame, in the order of the atlas.
+    pub frames: Vec<String>,
+}
+
+impl FrameAtlas {
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+enum FrameListing {
+    Folder(Handle<LoadedFolder>),
+    Manifest(Handle<FrameManifest>),
+    /// every frame is listed, waiting for the images to be loaded.
+    Frames(Vec<(String, Handle<Image>)>),
+    Built(FrameAtlas),
+    Failed,
+}
+
+struct FrameRequest {
+    source: FrameSource,
+    listing: FrameListing,
+}
+
+/**
+ Every frame atlas requested so far, by source.
+
+ the atlas of a source is built once, on the first [`FrameAtlases::request`], and shared by
+ every sprite drawn from it.
+*/
+#[derive(Resource, Default)]
+pub struct FrameAtlases {
+    requests: HashMap<String, FrameRequest>,
+}
+
+impl FrameAtlases {
+    /// the atlas of the frames at `source`, `None` until it is built.
+    pub fn request(&mut self, server: &AssetServer, source: &str) -> Option<&FrameAtlas> {
+        let request = self.requests.entry(source.to_owned()).or_insert_with(|| {
+            let source = FrameSource::parse(source);
+            // only native builds can list a folder.
+            let listing = if cfg!(target_arch = "wasm32") {
+                FrameListing::Manifest(server.load(source.manifest_path()))
+            } else {
+                FrameListing::Folder(server.load_folder(source.folder.clone()))
+            };
+            FrameRequest { source, listing }
+        });
+        match &request.listing {
+            FrameListing::Built(atlas) => Some(atlas),
+            _ => None,
+        }
+    }
+
+    /// whether the atlas of `source` will never be built, its frames missing or broken.
+    pub fn is_failed(&self, source: &str) -> bool {
+        self.requests
+            .get(source)
+            .is_some_and(|request| matches!(request.listing, FrameListing::Failed))
+    }
+}
+
+/// lists the frames of every request, then packs them in an atlas once they are loaded.
+pub fn build_frame_atlases(
+    mut book: ResMut<FrameAtlases>,
+    server: Res<AssetServer>,
+    folders: Res<Assets<LoadedFolder>>,
+    manifests: Res<Assets<FrameManifest>>,
+    mut images: ResMut<Assets<Image>>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    for (name, request) in &mut book.requests {
+        let source = &request.source;
+        let next = match &request.listing {
+            FrameListing::Folder(handle) => {
+                if server.load_state(handle) == LoadState::Failed {
+                    // the folder may only be there as a manifest.
+                    Some(FrameListing::Manifest(server.load(source.manifest_path())))
+                } else if let Some(folder) = folders.get(handle) {
+                    let mut frames: Vec<(String, Handle<Image>)> = folder
+                        .handles
+                        .iter()
+                        .filter(|handle| handle.type_id() == TypeId::of::<Image>())
+                        .filter_map(|handle| {
+                            let path = handle.path()?.path().to_string_lossy().replace('\\', "/");
+                            Some((path, handle.clone().typed::<Image>()))
+                        })
+                        .collect();
+                    frames.sort_by_key(|(path, _)| frame_order(path));
+                    // the manifest lists the whole folder, every pattern picks its frames from it.
+                    if cfg!(debug_assertions) {
+                        write_manifest(source, &frames);
+                    }
+                    frames.retain(|(path, _)| source.matches(path));
+                    Some(FrameListing::Frames(frames))
+                } else {
+                    None
+                }
+            }
+            FrameListing::Manifest(handle) => {
+                if server.load_state(handle) == LoadState::Failed {
+                    warn!("no frames at {:?}", name);
+                    Some(FrameListing::Failed)
+                } else if let Some(manifest) = manifests.get(handle) {
+                    let mut paths: Vec<&String> = manifest
+                        .frames
+                        .iter()
+                        .filter(|path| source.matches(path))
+                        .collect();
+                    paths.sort_by_key(|path| frame_order(path));
+                    Some(FrameListing::Frames(
+                        paths
+                            .into_iter()
+                            .map(|path| (path.clone(), server.load(path.clone())))
+                            .collect(),
+                    ))
+                } else {
+                    None
+                }
+            }
+            FrameListing::Frames(frames) => {
+                let failed = frames
+                    .iter()
+                    .find(|(_, image)| server.load_state(image) == LoadState::Failed);
+                if frames.is_empty() {
+                    warn!("no frames at {:?}", name);
+                    Some(FrameListing::Failed)
+                } else if let Some((path, _)) = failed {
+                    warn!("could not load the frame {:?} of {:?}", path, name);
+                    Some(FrameListing::Failed)
+                } else if frames.iter().all(|(_, image)| images.contains(image)) {
+                    Some(pack_frames(name, frames, &mut images, &mut atlases))
+                } else {
+                    None
+                }
+            }
+            FrameListing::Built(_) | FrameListing::Failed => None,
+        };
+        if let Some(next) = next {
+            request.listing = next;
+        }
+    }
+}
+
+fn pack_frames(
+    name: &str,
+    frames: &[(String, Handle<Image>)],
+    images: &mut Assets<Image>,
+    atlases: &mut Assets<TextureAtlas>,
+) -> FrameListing {
+    let mut builder = TextureAtlasBuilder::default().max_size(Vec2::splat(4096.));
+    for (_, handle) in frames {
+        builder.add_texture(handle.id(), images.get(handle).unwrap());
+    }
+    let packed = match builder.finish(images) {
+        Ok(atlas) => atlas,
+        Err(err) => {
+            warn!("could not pack the frames of {:?}: {}", name, err);
+            return FrameListing::Failed;
+        }
+    };
+    // the packer places frames where they fit, put them back in order.
+    let mut atlas = TextureAtlas::new_empty(packed.texture.clone(), packed.size);
+    for (_, handle) in frames {
+        atlas.add_texture(packed.textures[packed.get_texture_index(handle).unwrap()]);
+    }
+    FrameListing::Built(FrameAtlas {
+        atlas: atlases.add(atlas),
+        frames: frames.iter().map(|(path, _)| path.clone()).collect(),
+    })
+}
+
+/// writes the manifest of a folder in the asset folder, when it changed.
+fn write_manifest(source: &FrameSource, frames: &[(String, Handle<Image>)]) {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use bevy::asset::io::file::FileAssetReader;
+
+        let manifest = FrameManifest {
+            frames: frames.iter().map(|(path, _)| path.clone()).collect(),
+        };
+        let path = FileAssetReader::get_base_path()
+            .join("assets")
+            .join(source.manifest_path());
+        let unchanged = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| ron::de::from_bytes::<FrameManifest>(&bytes).ok())
+            .is_some_and(|old| old == manifest);
+        if unchanged {
+            return;
+        }
+        let written = ron::ser::to_string_pretty(&manifest, Default::default())
+            .map_err(|err| err.to_string())
+            .and_then(|text| std::fs::write(&path, text + "\n").map_err(|err| err.to_string()));
+        match written {
+            Ok(()) => info!("wrote frame manifest {:?}", path),
+            Err(err) => warn!("could not write frame manifest {:?}: {}", path, err),
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = (source, frames);
+}
@@ -3,15 +3,18 @@ use bevy::{
     ecs::{
         component::Component,
         entity::Entity,
-        query::{Or, With, Without},
+        event::EventWriter,
+        query::{Has, Or, With, Without},
         schedule::{common_conditions::in_state, IntoSystemConfigs},
-        system::{Query, Res},
+        system::{Commands, Query, Res},
     },
-    math::Vec3,
-    sprite::Sprite,
+    hierarchy::Children,
+    math::{Vec2, Vec3},
+    sprite::{Sprite, TextureAtlasSprite},
     time::Time,
     transform::components::Transform,
 };
+use bevy_rapier2d::control::KinematicCharacterController;
 use bevy_render::color::Color;
 
 use crate::{
@@ -20,7 +23,15 @@ use crate::{
     AppState,
 };
 
-use super::{battle::Stat, monster::Monster, player::Player, skill::SkillInfo};
+use super::{
+    battle::{Dead, Stat},
+    buff::{BuffKind, Buffs},
+    building::Building,
+    monster::Monster,
+    physics::move_unit,
+    player::Player,
+    skill::{cancel_cast, ChargeArrived, Interrupted, SkillCode, SkillInfo},
+};
 
 pub struct GameObjectPlugin;
 
@@ -28,7 +39,13 @@ impl Plugin for GameObjectPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            (selected_gameobject, move_gameobject).run_if(in_state(AppState::InGame)),
+            (
+                selected_gameobject,
+                move_gameobject,
+                charge_gameobject,
+                face_target,
+            )
+                .run_if(in_state(AppState::InGame)),
         );
     }
 }
@@ -39,24 +56,85 @@ pub struct Target(pub Option<Entity>);
 #[derive(Component, Debug)]
 pub struct MoveTarget(pub Option<Vec3>);
 
+/// rushes toward `target` at `speed`, ignoring the regular movement until it arrives.
+#[derive(Component, Debug)]
+pub struct Charging {
+    pub target: Entity,
+    pub speed: f32,
+    /// the charge skill, whose remaining effects land on arrival.
+    pub skill: SkillCode,
+    pub level: u32,
+}
+
+const CHARGE_STOP_DISTANCE: f32 = 60.;
+
+/// direction the unit is looking at, used by positional skills.
+#[derive(Component, Debug)]
+pub struct Facing(pub Vec2);
+
+impl Default for Facing {
+    fn default() -> Self {
+        Self(Vec2::X)
+    }
+}
+
+impl Facing {
+    /// whether `position` is in the back half of a unit standing at `origin`.
+    pub fn is_behind(&self, origin: Vec3, position: Vec3) -> bool {
+        (position - origin).truncate().dot(self.0) < 0.
+    }
+}
+
+/**
+ colors units by selection, death and stealth.
+
+ a unit drawn by a sprite sheet child gets the color on the sheet, its own sprite stays invisible.
+*/
 pub fn selected_gameobject(
     selected_list: Res<SelectedList>,
     mut entities: Query<
-        (Entity, &mut Sprite, Option<&Monster>, Option<&Player>),
+        (
+            Entity,
+            &mut Sprite,
+            Option<&Monster>,
+            Option<&Player>,
+            &Buffs,
+            Has<Dead>,
+            Option<&Children>,
+        ),
         Or<(With<Monster>, With<Player>)>,
     >,
+    mut sheets: Query<&mut TextureAtlasSprite>,
 ) {
-    for (ent, mut sprite, monster, player) in &mut entities {
-        if (*selected_list).entities.contains(&ent) {
-            sprite.color = Color::GREEN;
+    for (ent, mut sprite, monster, player, buffs, dead, children) in &mut entities {
+        let sheet = children.and_then(|children| {
+            children
+                .iter()
+                .copied()
+                .find(|child| sheets.contains(*child))
+        });
+        let mut color = if dead {
+            Color::GRAY
+        } else if (*selected_list).entities.contains(&ent) {
+            Color::GREEN
+        } else if sheet.is_some() {
+            Color::WHITE
+        } else if monster.is_some() {
+            Color::RED
+        } else if player.is_some() {
+            Color::BLUE
         } else {
-            sprite.color = if monster.is_some() {
-                Color::RED
-            } else if player.is_some() {
-                Color::BLUE
-            } else {
-                continue;
+            continue;
+        };
+        if buffs.has(BuffKind::Stealth) {
+            color.set_a(0.4);
+        }
+        match sheet.and_then(|child| sheets.get_mut(child).ok()) {
+            Some(mut sheet) => {
+                sheet.color = color;
+                sprite.color = Color::NONE;
             }
+            None => sprite.color = color,
         }
     }
 }
@@ -71,36 +149,51 @@ pub fn move_gameobject(
     mut players: Query<
         (
             Entity,
-            &mut Transform,
+            &Transform,
             &Stat,
             &Target,
             &MoveTarget,
             &ActionState,
             &mut BattleState,
             &mut SkillInfo,
+            &mut KinematicCharacterController,
+        ),
+        (
+            With<Player>,
+            Without<Monster>,
+            Without<Charging>,
+            Without<Dead>,
         ),
-        (With<Player>, Without<Monster>),
     >,
     mut monsters: Query<
         (
             Entity,
-            &mut Transform,
+            &Transform,
             &Stat,
             &Target,
             &MoveTarget,
             &ActionState,
             &mut BattleState,
             &mut SkillInfo,
+            &mut KinematicCharacterController,
         ),
-        (With<Monster>, Without<Player>),
+        (With<Monster>, Without<Player>, Without<Charging>),
     >,
+    buildings: Query<&Transform, (With<Building>, Without<Player>, Without<Monster>)>,
+    mut interrupted_evt: EventWriter<Interrupted>,
 ) {
-    for (ent, mut t, stat, target, mv_targ, a_state, mut b_state, mut skill) in &mut players {
+    for (ent, t, stat, target, mv_targ, a_state, mut b_state, mut skill, mut controller) in
+        &mut players
+    {
         let Some(tar) = target.0 else {
             continue;
         };
-        let Some((t_ent, mut t_t, t_stat, t_target, t_mv_targ, t_a_state, t_b_state, t_skill)) =
-            monsters.get(tar).ok()
+        // buildings can be targets too, only where they stand matters.
+        let Some(t_t) = monsters
+            .get(tar)
+            .map(|other| *other.1)
+            .ok()
+            .or_else(|| buildings.get(tar).ok().copied())
         else {
             continue;
         };
@@ -108,7 +201,7 @@ pub fn move_gameobject(
         match *a_state {
             ActionState::IDLE => {}
             ActionState::MOVE => {
-                skill.break_casting();
+                cancel_cast(ent, &mut skill, &mut interrupted_evt);
                 match mv_targ.0 {
                     Some(_ent) => {
                         let p_x = t.translation.x;
@@ -118,16 +211,22 @@ pub fn move_gameobject(
 
                         let direction = Vec3::new(t_x - p_x, t_y - p_y, 0.).normalize();
 
-                        t.translation += stat.speed * direction * time.delta_seconds();
+                        move_unit(
+                            &mut controller,
+                            (stat.speed * direction * time.delta_seconds()).truncate(),
+                        );
                     }
                     None => {
-                        t.translation.x += stat.speed * time.delta_seconds();
+                        move_unit(
+                            &mut controller,
+                            Vec2::new(stat.speed * time.delta_seconds(), 0.),
+                        );
                     }
                 };
             }
             ActionState::BATTLE => {
                 if *b_state == BattleState::MOVE {
-                    skill.break_casting();
+                    cancel_cast(ent, &mut skill, &mut interrupted_evt);
                     match target.0 {
                         Some(_ent) => {
                             let p_x = t.translation.x;
@@ -137,22 +236,43 @@ pub fn move_gameobject(
 
                             let direction = Vec3::new(t_x - p_x, t_y - p_y, 0.).normalize();
 
-                            t.translation += stat.speed * direction * time.delta_seconds();
+                            move_unit(
+                                &mut controller,
+                                (stat.speed * direction * time.delta_seconds()).truncate(),
+                            );
                         }
                         None => {
-                            t.translation.x += stat.speed * time.delta_seconds();
+                            move_unit(
+                                &mut controller,
+                                Vec2::new(stat.speed * time.delta_seconds(), 0.),
+                            );
                         }
                     };
+                } else if *b_state == BattleState::RUNAWAY {
+                    // kiting, backs off from a target standing inside the minimum range.
+                    let direction = (t.translation - t_t.translation)
+                        .truncate()
+                        .normalize_or_zero();
+                    move_unit(
+                        &mut controller,
+                        stat.speed * direction * time.delta_seconds(),
+                    );
                 }
             }
         }
     }
-    for (ent, mut t, stat, target, mv_targ, a_state, mut b_state, mut skill) in &mut monsters {
+    for (ent, t, stat, target, mv_targ, a_state, mut b_state, mut skill, mut controller) in
+        &mut monsters
+    {
         let Some(tar) = target.0 else {
             continue;
         };
-        let Some((t_ent, mut t_t, t_stat, t_target, t_mv_targ, t_a_state, t_b_state, t_skill)) =
-            players.get(tar).ok()
+        // buildings can be targets too, only where they stand matters.
+        let Some(t_t) = players
+            .get(tar)
+            .map(|other| *other.1)
+            .ok()
+            .or_else(|| buildings.get(tar).ok().copied())
         else {
             continue;
         };
@@ -160,7 +280,7 @@ pub fn move_gameobject(
         match *a_state {
             ActionState::IDLE => {}
             ActionState::MOVE => {
-                skill.break_casting();
+                cancel_cast(ent, &mut skill, &mut interrupted_evt);
                 match mv_targ.0 {
                     Some(_ent) => {
                         let p_x = t.translation.x;
@@ -170,16 +290,22 @@ pub fn move_gameobject(
 
                         let direction = Vec3::new(t_x - p_x, t_y - p_y, 0.).normalize();
 
-                        t.translation += stat.speed * direction * time.delta_seconds();
+                        move_unit(
+                            &mut controller,
+                            (stat.speed * direction * time.delta_seconds()).truncate(),
+                        );
                     }
                     None => {
-                        t.translation.x += stat.speed * time.delta_seconds();
+                        move_unit(
+                            &mut controller,
+                            Vec2::new(stat.speed * time.delta_seconds(), 0.),
+                        );
                     }
                 };
             }
             ActionState::BATTLE => {
                 if *b_state == BattleState::MOVE {
-                    skill.break_casting();
+                    cancel_cast(ent, &mut skill, &mut interrupted_evt);
                     match target.0 {
                         Some(_ent) => {
                             let p_x = t.translation.x;
@@ -189,14 +315,82 @@ pub fn move_gameobject(
 
                             let direction = Vec3::new(t_x - p_x, t_y - p_y, 0.).normalize();
 
-                            t.translation += stat.speed * direction * time.delta_seconds();
+                            move_unit(
+                                &mut controller,
+                                (stat.speed * direction * time.delta_seconds()).truncate(),
+                            );
                         }
                         None => {
-                            t.translation.x += stat.speed * time.delta_seconds();
+                            move_unit(
+                                &mut controller,
+                                Vec2::new(stat.speed * time.delta_seconds(), 0.),
+                            );
                         }
                     };
+                } else if matches!(*b_state, BattleState::RUNAWAY | BattleState::FLEEING) {
+                    // backing off from a target too close, or fleeing at low health.
+                    let direction = (t.translation - t_t.translation)
+                        .truncate()
+                        .normalize_or_zero();
+                    move_unit(
+                        &mut controller,
+                        stat.speed * direction * time.delta_seconds(),
+                    );
                 }
             }
         }
     }
 }
+
+pub fn charge_gameobject(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut chargers: Query<(
+        Entity,
+        &Transform,
+        &Charging,
+        &mut KinematicCharacterController,
+    )>,
+    targets: Query<&Transform, Without<Charging>>,
+    mut arrived_evt: EventWriter<ChargeArrived>,
+) {
+    for (ent, t, charging, mut controller) in &mut chargers {
+        let Ok(targ_t) = targets.get(charging.target) else {
+            commands.entity(ent).remove::<Charging>();
+            continue;
+        };
+        let diff = (targ_t.translation - t.translation).truncate();
+        let step = charging.speed * time.delta_seconds();
+        if diff.length() - step <= CHARGE_STOP_DISTANCE {
+            move_unit(
+                &mut controller,
+                diff.normalize_or_zero() * (diff.length() - CHARGE_STOP_DISTANCE).max(0.),
+            );
+            commands.entity(ent).remove::<Charging>();
+            arrived_evt.send(ChargeArrived {
+                caster: ent,
+                target: charging.target,
+                skill: charging.skill,
+                level: charging.level,
+            });
+        } else {
+            move_unit(&mut controller, diff.normalize_or_zero() * step);
+        }
+    }
+}
+
+/// units in battle turn toward their target.
+pub fn face_target(
+    mut entities: Query<(&Transform, &Target, &mut Facing), Without<Dead>>,
+    targets: Query<&Transform, With<Facing>>,
+) {
+    for (t, target, mut facing) in &mut entities {
+        let Some(targ_t) = target.0.and_then(|targ| targets.get(targ).ok()) else {
+            continue;
+        };
+        let direction = (targ_t.translation - t.translation).truncate();
+        if direction != Vec2::ZERO {
+            facing.0 = direction.normalize();
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::Assets,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{Event, EventReader, EventWriter},
+        query::{Or, With, Without},
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res, ResMut},
+    },
+    math::{Vec2, Vec3},
+    prelude::default,
+    render::{
+        color::Color,
+        mesh::{shape, Mesh},
+    },
+    sprite::{ColorMaterial, MaterialMesh2dBundle},
+    time::{Time, Timer, TimerMode},
+    transform::components::Transform,
+};
+use rand::Rng;
+use serde::{de, Deserialize, Deserializer};
+use std::f32::consts::TAU;
+
+use crate::AppState;
+
+use super::{
+    battle::{Attacked, Damage, Dead, Heal, HealType},
+    buff::{BuffKind, Buffs},
+    components::{Team, TeamType},
+    monster::Monster,
+    player::Player,
+    render_layer::RenderLayer,
+    skill_definition::{scaled, Relation, SkillEffect},
+};
+
+pub struct GroundEffectPlugin;
+
+impl Plugin for GroundEffectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SpawnGroundEffect>()
+            .add_event::<GroundEffectEntered>()
+            .add_event::<GroundEffectExited>()
+            .add_systems(
+                Update,
+                (
+                    spawn_ground_effects,
+                    track_ground_effects,
+                    apply_zone_auras,
+                    tick_ground_effects,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum ZoneShape {
+    Circle { radius: f32 },
+    Rect { width: f32, height: f32 },
+}
+
+impl ZoneShape {
+    pub fn contains(&self, center: Vec3, point: Vec3) -> bool {
+        let diff = (point - center).truncate();
+        match self {
+            ZoneShape::Circle { radius } => diff.length() <= *radius,
+            ZoneShape::Rect { width, height } => {
+                diff.x.abs() <= width / 2. && diff.y.abs() <= height / 2.
+            }
+        }
+    }
+
+    /// uniformly picked point of the shape, relative to its center.
+    pub fn random_point(&self, rng: &mut impl Rng) -> Vec2 {
+        match self {
+            ZoneShape::Circle { radius } => {
+                let angle = rng.gen::<f32>() * TAU;
+                Vec2::new(angle.cos(), angle.sin()) * *radius * rng.gen::<f32>().sqrt()
+            }
+            ZoneShape::Rect { width, height } => Vec2::new(
+                (rng.gen::<f32>() - 0.5) * width,
+                (rng.gen::<f32>() - 0.5) * height,
+            ),
+        }
+    }
+
+    fn mesh(&self) -> Mesh {
+        match self {
+            ZoneShape::Circle { radius } => shape::Circle::new(*radius).into(),
+            ZoneShape::Rect { width, height } => {
+                shape::Quad::new(Vec2::new(*width, *height)).into()
+            }
+        }
+    }
+}
+
+/**
+ Data driven description of a zone left on the ground, like a fire patch or a blizzard.
+
+ every `interval` seconds the `effects` land on the units of the relation standing inside,
+ and the `aura` buff stays on them from the moment they enter until they leave.
+ only damage, heals and buffs make sense on a zone, a file with any other skill effect is rejected.
+*/
+#[derive(Deserialize, Debug, Clone)]
+pub struct GroundEffectDefinition {
+    pub shape: ZoneShape,
+    pub affects: Relation,
+    pub duration: f32,
+    pub interval: f32,
+    #[serde(default, deserialize_with = "zone_effects")]
+    pub effects: Vec<SkillEffect>,
+    #[serde(default)]
+    pub aura: Option<BuffKind>,
+    pub color: (f32, f32, f32, f32),
+}
+
+/// a zone only deals damage, heals and buffs, anything else can't be applied from the ground.
+fn zone_effects<'de, D>(deserializer: D) -> Result<Vec<SkillEffect>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let effects = Vec::<SkillEffect>::deserialize(deserializer)?;
+    if let Some(effect) = effects.iter().find(|effect| {
+        !matches!(
+            effect,
+            SkillEffect::Damage { .. }
+                | SkillEffect::Heal { .. }
+                | SkillEffect::HealOverTime { .. }
+                | SkillEffect::ApplyBuff { .. }
+        )
+    }) {
+        return Err(de::Error::custom(format!(
+            "{:?} can't be left on the ground",
+            effect
+        )));
+    }
+    Ok(effects)
+}
+
+#[derive(Component, Debug)]
+pub struct GroundEffect {
+    pub definition: GroundEffectDefinition,
+    /// damage and heals are attributed to the owner.
+    pub owner: Entity,
+    pub team: TeamType,
+    pub level: u32,
+    duration: Timer,
+    interval: Timer,
+    inside: Vec<Entity>,
+}
+
+/// places a [`GroundEffect`] at `position`.
+#[derive(Event)]
+pub struct SpawnGroundEffect {
+    pub definition: GroundEffectDefinition,
+    pub owner: Entity,
+    pub team: TeamType,
+    pub level: u32,
+    pub position: Vec3,
+}
+
+#[derive(Event)]
+pub struct GroundEffectEntered {
+    pub zone: Entity,
+    pub unit: Entity,
+}
+
+#[derive(Event)]
+pub struct GroundEffectExited {
+    pub zone: Entity,
+    pub unit: Entity,
+}
+
+pub fn spawn_ground_effects(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut spawn_evt: EventReader<SpawnGroundEffect>,
+) {
+    for evt in spawn_evt.read() {
+        let definition = evt.definition.clone();
+        let (r, g, b, a) = definition.color;
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: meshes.add(definition.shape.mesh()).into(),
+                material: materials.add(ColorMaterial::from(Color::rgba(r, g, b, a))),
+                transform: Transform::from_translation(
+                    evt.position.truncate().extend(RenderLayer::Decals.z()),
+                ),
+                ..default()
+            },
+            GroundEffect {
+                duration: Timer::from_seconds(definition.duration, TimerMode::Once),
+                interval: Timer::from_seconds(definition.interval, TimerMode::Repeating),
+                definition,
+                owner: evt.owner,
+                team: evt.team,
+                level: evt.level,
+                inside: Vec::new(),
+            },
+        ));
+    }
+}
+
+/**
+ keeps track of who stands in every zone, sending enter and exit events.
+
+ every unit leaves an expired zone.
+*/
+pub fn track_ground_effects(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut zones: Query<(Entity, &Transform, &mut GroundEffect)>,
+    units: Query<
+        (Entity, &Transform, &Team),
+        (
+            Or<(With<Player>, With<Monster>)>,
+            Without<Dead>,
+            Without<GroundEffect>,
+        ),
+    >,
+    mut enter_evt: EventWriter<GroundEffectEntered>,
+    mut exit_evt: EventWriter<GroundEffectExited>,
+) {
+    for (zone_ent, zone_t, mut zone) in &mut zones {
+        zone.duration.tick(time.delta());
+        let expired = zone.duration.finished();
+
+        let current: Vec<Entity> = if expired {
+            Vec::new()
+        } else {
+            units
+                .iter()
+                .filter(|(_, t, team)| {
+                    zone.definition.affects.matches(&zone.team, &team.0)
+                        && zone
+                            .definition
+                            .shape
+                            .contains(zone_t.translation, t.translation)
+                })
+                .map(|(ent, _, _)| ent)
+                .collect()
+        };
+
+        for unit in current.iter().filter(|ent| !zone.inside.contains(ent)) {
+            enter_evt.send(GroundEffectEntered {
+                zone: zone_ent,
+                unit: *unit,
+            });
+        }
+        for unit in zone.inside.iter().filter(|ent| !current.contains(ent)) {
+            exit_evt.send(GroundEffectExited {
+                zone: zone_ent,
+                unit: *unit,
+            });
+        }
+        zone.inside = current;
+
+        if expired {
+            commands.entity(zone_ent).despawn();
+        }
+    }
+}
+
+/// puts the aura of a zone on the units entering it and takes it off the ones leaving.
+pub fn apply_zone_auras(
+    zones: Query<&GroundEffect>,
+    mut units: Query<&mut Buffs, Without<GroundEffect>>,
+    mut enter_evt: EventReader<GroundEffectEntered>,
+    mut exit_evt: EventReader<GroundEffectExited>,
+) {
+    for evt in enter_evt.read() {
+        let Ok(zone) = zones.get(evt.zone) else {
+            continue;
+        };
+        if let (Some(aura), Ok(mut buffs)) = (zone.definition.aura, units.get_mut(evt.unit)) {
+            buffs.add(aura, zone.owner, zone.duration.remaining_secs());
+        }
+    }
+    // an expired zone is despawned at the end of the frame, it is still here for its exits.
+    for evt in exit_evt.read() {
+        let Ok(zone) = zones.get(evt.zone) else {
+            continue;
+        };
+        if let (Some(aura), Ok(mut buffs)) = (zone.definition.aura, units.get_mut(evt.unit)) {
+            buffs.remove(aura);
+        }
+    }
+}
+
+/// applies the zone effects to the units inside every time the interval finishes.
+pub fn tick_ground_effects(
+    time: Res<Time>,
+    mut zones: Query<&mut GroundEffect>,
+    mut units: Query<&mut Buffs, Without<GroundEffect>>,
+    mut attacked_evt: EventWriter<Attacked>,
+    mut damage_evt: EventWriter<Damage>,
+    mut heal_evt: EventWriter<Heal>,
+) {
+    for mut zone in &mut zones {
+        zone.interval.tick(time.delta());
+        for _ in 0..zone.interval.times_finished_this_tick() {
+            for unit in &zone.inside {
+                for effect in &zone.definition.effects {
+                    match effect {
+                        SkillEffect::Damage {
+                            amount,
+                            per_level,
+                            damage_type,
+                            ..
+                        } => {
+                            damage_evt.send(Damage {
+                                attacker: zone.owner,
+                                damage: scaled(*amount, *per_level, zone.level),
+                                damage_type: damage_type.clone(),
+                                attacked: *unit,
+                            });
+                            attacked_evt.send(Attacked::new(zone.owner, *unit));
+                        }
+                        SkillEffect::Heal { amount, per_level } => heal_evt.send(Heal {
+                            healer: zone.owner,
+                            value: scaled(*amount, *per_level, zone.level),
+                            heal_type: HealType::dot,
+                            healed: *unit,
+                        }),
+                        SkillEffect::HealOverTime {
+                            buff,
+                            amount,
+                            per_level,
+                            duration,
+                            interval,
+                        } => {
+                            if let Ok(mut buffs) = units.get_mut(*unit) {
+                                buffs.add_periodic(
+                                    *buff,
+                                    zone.owner,
+                                    *duration,
+                                    *interval,
+                                    scaled(*amount, *per_level, zone.level),
+                                );
+                            }
+                        }
+                        SkillEffect::ApplyBuff { buff, duration } => {
+                            if let Ok(mut buffs) = units.get_mut(*unit) {
+                                buffs.add(*buff, zone.owner, *duration);
+                            }
+                        }
+                        // rejected when the definition is loaded.
+                        _ => unreachable!("{:?} on the ground", effect),
+                    }
+                }
+            }
+        }
+    }
+}
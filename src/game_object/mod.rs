@@ -1,13 +1,30 @@
+pub mod animation;
 pub mod battle;
+pub mod buff;
+pub mod build_mode;
+pub mod building;
 pub mod camera;
+pub mod chunk;
 pub mod components;
+pub mod encounter;
+pub mod environment;
+pub mod frame_atlas;
 pub mod game_object;
+pub mod ground_effect;
 pub mod monster;
+pub mod monster_template;
+pub mod pet;
+pub mod physics;
 pub mod player;
 pub mod projectile;
+pub mod render_layer;
+pub mod rotation;
 pub mod skill;
+pub mod skill_definition;
+pub mod spawner;
 pub mod system;
 pub use game_object::*;
 pub mod command;
 pub mod tilemap;
 pub mod tree;
+pub mod worldgen;
@@ -1,42 +1,73 @@
 use bevy::{
-    app::{App, Plugin, Update},
+    app::{App, Plugin, Startup, Update},
+    asset::{AssetApp, AssetServer, Assets, Handle},
     ecs::{
         bundle::Bundle,
         component::Component,
         entity::Entity,
+        event::{Event, EventReader, EventWriter},
         query::{With, Without},
         schedule::{common_conditions::in_state, IntoSystemConfigs},
-        system::{Commands, Query, Res},
+        system::{Commands, Query, Res, ResMut, Resource, SystemParam},
     },
+    hierarchy::BuildChildren,
+    log::info,
     math::{Vec2, Vec3},
     prelude::default,
     render::color::Color,
-    sprite::{Sprite, SpriteBundle},
+    sprite::{Sprite, SpriteBundle, SpriteSheetBundle, TextureAtlas, TextureAtlasSprite},
     time::Time,
     transform::components::Transform,
 };
 
 use crate::{
+    data::RonLoader,
     game_object::{system::spawn_healthbar, Target},
+    resources::resource::Stockpile,
     states::{ActionState, BattleState},
     AppState, Stat,
 };
 
+use bevy_rapier2d::control::KinematicCharacterController;
+use rand::Rng;
+use serde::Deserialize;
+
 use super::{
+    animation::AnimatedSpriteBundle,
+    battle::{die, Dead, Died, Exp},
+    buff::{BuffKind, Buffs},
+    building::Building,
+    chunk::Dormant,
     components::{GameObjectBundle, Team, TeamType},
-    player::Player,
-    skill::SkillInfo,
-    MoveTarget,
+    monster_template::{LootEntry, MonsterRegistry, MonsterSprite, MonsterTemplate},
+    move_gameobject,
+    pet::Pet,
+    physics::{move_unit, spawn_hitbox, UnitPhysicsBundle},
+    player::{Class, Player},
+    render_layer::YSort,
+    skill::{cancel_cast, Interrupted, SkillCode, SkillInfo},
+    Facing, MoveTarget,
 };
 
 pub struct MonsterPlugin;
 
 impl Plugin for MonsterPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            trig_monster_action.run_if(in_state(AppState::InGame)),
-        );
+        app.init_asset::<MonsterRegistry>()
+            .register_asset_loader(RonLoader::<MonsterRegistry>::new(&["registry.ron"]))
+            .init_resource::<MonsterBook>()
+            .add_event::<LootDropped>()
+            .add_systems(Startup, load_monster_book)
+            .add_systems(
+                Update,
+                (
+                    trig_monster_action,
+                    (drop_loot, announce_loot, collect_loot).chain().after(die),
+                    grant_exp.after(die),
+                    (leash_monsters, flee_at_low_health).before(move_gameobject),
+                )
+                    .run_if(in_state(AppState::InGame)),
+            );
     }
 }
 
@@ -44,8 +75,42 @@ impl Plugin for MonsterPlugin {
 pub struct Monster {
     name: String,
     exp: f32,
-    //drop_item,
-    //drop_item chance,
+    /// used when nothing of its rotation is ready.
+    pub auto_attack: SkillCode,
+    loot: Vec<LootEntry>,
+}
+
+/// how far a monster follows its target away from its spawn point.
+pub const LEASH_RADIUS: f32 = 800.;
+/// an evading monster that can't get home in time gives up on it where it stands.
+pub const EVADE_TIMEOUT: f32 = 15.;
+/// how close to its spawn point an evading monster has to get.
+const HOME_RADIUS: f32 = 10.;
+
+/**
+ The spawn point a monster is tied to.
+
+ pulled further than `radius` away from `home`, the monster drops its target and evades back,
+ immune to damage, and is back to full health once it arrives.
+*/
+#[derive(Component, Debug)]
+pub struct Leash {
+    pub home: Vec3,
+    pub radius: f32,
+}
+
+/**
+ Makes a monster run away once, when its health drops under `health_ratio`.
+
+ every monster within `call_radius` that isn't fighting yet joins in on the fleeing monster's target.
+*/
+#[derive(Component, Deserialize, Debug, Clone)]
+pub struct Flee {
+    pub health_ratio: f32,
+    pub call_radius: f32,
+    pub duration: f32,
+    #[serde(skip)]
+    fled: bool,
 }
 
 #[derive(Bundle)]
@@ -55,43 +120,249 @@ pub struct MonsterBundle {
 }
 
 impl Monster {
-    pub fn new(name: String, exp: f32) -> Self {
-        Self { name, exp }
+    pub fn new(name: String, exp: f32, auto_attack: SkillCode, loot: Vec<LootEntry>) -> Self {
+        Self {
+            name,
+            exp,
+            auto_attack,
+            loot,
+        }
     }
 }
 
-pub fn spawn_monster(
+/**
+ Handle of the [`MonsterRegistry`] file.
+
+ templates are looked up on every spawn, so a hot reloaded file applies to the next monster.
+*/
+#[derive(Resource, Default)]
+pub struct MonsterBook {
+    registry: Handle<MonsterRegistry>,
+}
+
+pub fn load_monster_book(mut book: ResMut<MonsterBook>, server: Res<AssetServer>) {
+    book.registry = server.load("monsters/monsters.registry.ron");
+}
+
+/// everything [`spawn_monster_by_id`] needs to build a monster from its template.
+#[derive(SystemParam)]
+pub struct MonsterTemplates<'w> {
+    book: Res<'w, MonsterBook>,
+    registries: Res<'w, Assets<MonsterRegistry>>,
+    server: Res<'w, AssetServer>,
+    atlases: ResMut<'w, Assets<TextureAtlas>>,
+}
+
+impl<'w> MonsterTemplates<'w> {
+    pub fn get(&self, id: &str) -> Option<&MonsterTemplate> {
+        self.registries
+            .get(&self.book.registry)
+            .and_then(|registry| registry.0.get(id))
+    }
+}
+
+/**
+ spawns the monster of the template `id` at the given level.
+
+ returns `None` while the registry is loading or when there is no such template.
+*/
+pub fn spawn_monster_by_id(
     commands: &mut Commands,
-    name: String,
-    stat: Stat,
+    templates: &mut MonsterTemplates,
+    id: &str,
+    level: u32,
     transform: Transform,
-) -> Entity {
-    let id = commands
-        .spawn(MonsterBundle {
-            monster: Monster::new(name, 10.),
-            game_object: GameObjectBundle {
-                sprite: SpriteBundle {
-                    sprite: Sprite {
-                        color: Color::RED,
-                        custom_size: Some(Vec2::new(50., 80.)),
-                        ..default()
-                    },
-                    transform,
+) -> Option<Entity> {
+    let template = templates.get(id)?.clone();
+    let size = Vec2::new(template.size.0, template.size.1);
+    let mut sprite = SpriteBundle {
+        sprite: Sprite {
+            custom_size: Some(size),
+            ..default()
+        },
+        transform,
+        ..default()
+    };
+    let mut sheet = None;
+    let mut animated = None;
+    match &template.sprite {
+        MonsterSprite::Color((r, g, b)) => sprite.sprite.color = Color::rgb(*r, *g, *b),
+        MonsterSprite::Image(path) => sprite.texture = templates.server.load(path),
+        MonsterSprite::Atlas {
+            path,
+            tile_size,
+            columns,
+            rows,
+            index,
+        } => {
+            // the sheet is drawn by a child, the unit keeps an invisible sprite for its size.
+            sprite.sprite.color = Color::NONE;
+            let atlas = TextureAtlas::from_grid(
+                templates.server.load(path),
+                Vec2::new(tile_size.0, tile_size.1),
+                *columns,
+                *rows,
+                None,
+                None,
+            );
+            sheet = Some(SpriteSheetBundle {
+                texture_atlas: templates.atlases.add(atlas),
+                sprite: TextureAtlasSprite {
+                    index: *index,
+                    custom_size: Some(size),
                     ..default()
                 },
-                move_target: MoveTarget(None),
-                stat,
-                target: Target(None),
-                action_state: ActionState::IDLE,
-                battle_state: BattleState::IDLE,
-                skill_info: SkillInfo::new(),
-                team: Team(TeamType::MONSTER),
+                ..default()
+            });
+        }
+        MonsterSprite::Animated(path) => {
+            sprite.sprite.color = Color::NONE;
+            animated = Some(AnimatedSpriteBundle::new(&templates.server, path, size));
+        }
+    }
+
+    let stats = template.stats;
+    let ent = commands
+        .spawn((
+            MonsterBundle {
+                monster: Monster::new(
+                    template.name.clone(),
+                    template.exp(level),
+                    template.auto_attack,
+                    template.loot.clone(),
+                ),
+                game_object: GameObjectBundle {
+                    sprite,
+                    move_target: MoveTarget(None),
+                    stat: Stat::new(
+                        stats.speed,
+                        template.hp(level),
+                        level,
+                        stats.detect_range,
+                        stats.attack_range,
+                    ),
+                    target: Target(None),
+                    action_state: ActionState::IDLE,
+                    battle_state: BattleState::IDLE,
+                    skill_info: SkillInfo::new(),
+                    buffs: Buffs::default(),
+                    facing: Facing::default(),
+                    physics: UnitPhysicsBundle::new(size),
+                    y_sort: YSort::feet(size.y),
+                    team: Team(TeamType::MONSTER),
+                },
             },
-        })
+            template.rotation,
+        ))
         .id();
+    if let Some(radius) = template.ai.leash {
+        commands.entity(ent).insert(Leash {
+            home: transform.translation,
+            radius,
+        });
+    }
+    if let Some(flee) = template.ai.flee {
+        commands.entity(ent).insert(flee);
+    }
+    if let Some(sheet) = sheet {
+        commands.entity(ent).with_children(|parent| {
+            parent.spawn(sheet);
+        });
+    }
+    if let Some(animated) = animated {
+        commands.entity(ent).with_children(|parent| {
+            parent.spawn(animated);
+        });
+    }
     let t = Transform::from_xyz(10000., 10000., 10000.);
-    spawn_healthbar(commands, id, t);
-    id
+    spawn_healthbar(commands, ent, t);
+    spawn_hitbox(commands, ent, size);
+    Some(ent)
+}
+
+/// an item left by a dying monster.
+#[derive(Event)]
+pub struct LootDropped {
+    pub item: String,
+    pub count: u32,
+    pub position: Vec3,
+}
+
+/// rolls the loot table of every monster that died.
+pub fn drop_loot(
+    mut died_evt: EventReader<Died>,
+    monsters: Query<(&Transform, &Monster)>,
+    mut loot_evt: EventWriter<LootDropped>,
+) {
+    let mut rng = rand::thread_rng();
+    for died in died_evt.read() {
+        let Ok((t, monster)) = monsters.get(died.entity) else {
+            continue;
+        };
+        for entry in &monster.loot {
+            if rng.gen::<f32>() >= entry.chance {
+                continue;
+            }
+            let count = rng.gen_range(entry.min..=entry.max.max(entry.min));
+            loot_evt.send(LootDropped {
+                item: entry.item.clone(),
+                count,
+                position: t.translation,
+            });
+        }
+    }
+}
+
+/// logs the loot left on the ground.
+pub fn announce_loot(mut loot_evt: EventReader<LootDropped>) {
+    for loot in loot_evt.read() {
+        info!(
+            "{} x{} dropped at {:?}",
+            loot.item, loot.count, loot.position
+        );
+    }
+}
+
+/// puts the loot in the players' stockpile.
+pub fn collect_loot(mut loot_evt: EventReader<LootDropped>, mut stockpile: ResMut<Stockpile>) {
+    for loot in loot_evt.read() {
+        stockpile.add(&loot.item, loot.count);
+    }
+}
+
+/**
+ shares the exp of every monster that died between the living players, pets left out.
+
+ a player going up a level gets the base stats of its class for that level, back at full health.
+*/
+pub fn grant_exp(
+    mut died_evt: EventReader<Died>,
+    monsters: Query<&Monster>,
+    mut players: Query<
+        (&Player, &Class, &mut Stat, &mut Exp),
+        (Without<Pet>, Without<Dead>, Without<Monster>),
+    >,
+) {
+    for died in died_evt.read() {
+        let Ok(monster) = monsters.get(died.entity) else {
+            continue;
+        };
+        let count = players.iter().count();
+        if count == 0 {
+            continue;
+        }
+        let share = monster.exp / count as f32;
+        info!("{} killed, {} exp for each player", monster.name, share);
+        for (player, class, mut stat, mut exp) in &mut players {
+            let levels = exp.gain(share, stat.level);
+            if levels == 0 {
+                continue;
+            }
+            let level = stat.level + levels;
+            *stat = class.base_stat(level);
+            info!("{} reached level {}", player.name(), level);
+        }
+    }
 }
 
 pub fn trig_monster_action(
@@ -106,9 +377,10 @@ pub fn trig_monster_action(
             &mut BattleState,
             &mut SkillInfo,
         ),
-        (With<Monster>, Without<Player>),
+        (With<Monster>, Without<Player>, Without<Dormant>),
     >,
     players: Query<&mut Transform, With<Player>>,
+    buildings: Query<&Transform, (With<Building>, Without<Player>, Without<Monster>)>,
 ) {
     for (m_transform, m_stat, m_target, mut action_state, mut battle_state, mut skill_info) in
         &mut monsters
@@ -122,11 +394,14 @@ pub fn trig_monster_action(
             // if target exists.
             Some(ent) => {
                 // if get monster entity successfully.
-                match players.get(ent).ok() {
+                match players.get(ent).or_else(|_| buildings.get(ent)).ok() {
                     Some(p_t) => {
                         let dist = m_transform.translation.distance(p_t.translation);
                         // info!("dist : {:?}", dist);
-                        if dist <= m_stat.detect_range as f32 {
+                        // fleeing monsters keep running however far they get.
+                        if dist <= m_stat.detect_range as f32
+                            || *battle_state == BattleState::FLEEING
+                        {
                             *action_state = ActionState::BATTLE;
                         } else {
                             if *battle_state != BattleState::CASTING && dist > m_stat.attack_range {
@@ -145,3 +420,103 @@ pub fn trig_monster_action(
         }
     }
 }
+
+/**
+ sends monsters pulled too far from their [`Leash`] back home.
+
+ an evading monster is kept in [`BattleState::EVADING`] without a target until it's home,
+ where it gets its health back.
+*/
+pub fn leash_monsters(
+    time: Res<Time>,
+    mut monsters: Query<
+        (
+            Entity,
+            &Transform,
+            &Leash,
+            &mut Stat,
+            &mut Target,
+            &mut BattleState,
+            &mut Buffs,
+            &mut SkillInfo,
+            &mut KinematicCharacterController,
+        ),
+        (With<Monster>, Without<Dead>),
+    >,
+    mut interrupted_evt: EventWriter<Interrupted>,
+) {
+    for (ent, t, leash, mut stat, mut target, mut b_state, mut buffs, mut skill, mut controller) in
+        &mut monsters
+    {
+        let to_home = (leash.home - t.translation).truncate();
+        if !buffs.has(BuffKind::Evading) {
+            // timed out on the way home.
+            if *b_state == BattleState::EVADING {
+                *b_state = BattleState::IDLE;
+            }
+            if target.0.is_none() || to_home.length() <= leash.radius {
+                continue;
+            }
+            buffs.remove(BuffKind::Fleeing);
+            buffs.add(BuffKind::Evading, ent, EVADE_TIMEOUT);
+            cancel_cast(ent, &mut skill, &mut interrupted_evt);
+        }
+
+        *target = Target(None);
+        let step = stat.speed * time.delta_seconds();
+        if to_home.length() <= HOME_RADIUS + step {
+            move_unit(&mut controller, to_home);
+            stat.hp.current = stat.hp.max;
+            buffs.remove(BuffKind::Evading);
+            *b_state = BattleState::IDLE;
+        } else {
+            move_unit(&mut controller, to_home.normalize_or_zero() * step);
+            *b_state = BattleState::EVADING;
+        }
+    }
+}
+
+/// makes monsters with a [`Flee`] run away at low health, pulling their idle neighbours in.
+pub fn flee_at_low_health(
+    mut monsters: Query<
+        (
+            Entity,
+            &Transform,
+            &Stat,
+            &mut Target,
+            &mut ActionState,
+            &mut Buffs,
+            Option<&mut Flee>,
+        ),
+        (With<Monster>, Without<Dead>),
+    >,
+) {
+    let mut calls = Vec::new();
+    for (ent, t, stat, target, _, mut buffs, flee) in &mut monsters {
+        let (Some(mut flee), Some(attacker)) = (flee, target.0) else {
+            continue;
+        };
+        if flee.fled
+            || buffs.has(BuffKind::Evading)
+            || stat.hp.current > stat.hp.max * flee.health_ratio
+        {
+            continue;
+        }
+        flee.fled = true;
+        buffs.add(BuffKind::Fleeing, ent, flee.duration);
+        calls.push((t.translation, flee.call_radius, attacker));
+    }
+
+    for (position, radius, attacker) in calls {
+        for (_, t, _, mut target, mut a_state, buffs, _) in &mut monsters {
+            if target.0.is_some()
+                || buffs.has(BuffKind::Evading)
+                || t.translation.distance(position) > radius
+            {
+                continue;
+            }
+            *target = Target(Some(attacker));
+            *a_state = ActionState::BATTLE;
+        }
+    }
+}
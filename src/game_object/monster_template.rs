@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use bevy::{asset::Asset, reflect::TypePath};
+use serde::Deserialize;
+
+use super::{
+    monster::{Flee, LEASH_RADIUS},
+    rotation::Rotation,
+    skill::SkillCode,
+    skill_definition::scaled,
+};
+
+/**
+ Every [`MonsterTemplate`] by id, loaded from `monsters/monsters.registry.ron`.
+
+ adding an entry to the file is enough to make a new monster spawnable by its id.
+*/
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct MonsterRegistry(pub HashMap<String, MonsterTemplate>);
+
+/// Data driven description of a kind of monster, scaled to the level it is spawned at.
+#[derive(Deserialize, Debug, Clone)]
+pub struct MonsterTemplate {
+    pub name: String,
+    pub stats: BaseStats,
+    #[serde(default)]
+    pub exp: f32,
+    #[serde(default)]
+    pub exp_per_level: f32,
+    pub sprite: MonsterSprite,
+    pub size: (f32, f32),
+    #[serde(default = "default_auto_attack")]
+    pub auto_attack: SkillCode,
+    #[serde(default)]
+    pub rotation: Rotation,
+    #[serde(default)]
+    pub ai: AiProfile,
+    #[serde(default)]
+    pub loot: Vec<LootEntry>,
+}
+
+fn default_auto_attack() -> SkillCode {
+    SkillCode::MagicBolt
+}
+
+impl MonsterTemplate {
+    pub fn hp(&self, level: u32) -> f32 {
+        scaled(self.stats.hp, self.stats.hp_per_level, level)
+    }
+
+    pub fn exp(&self, level: u32) -> f32 {
+        scaled(self.exp, self.exp_per_level, level)
+    }
+}
+
+/**
+ stats of the template at level 1.
+
+ only the hp grow with the level, like the class stats of the players. speed and ranges stay
+ the same at every level so that a high level monster still moves and aggroes like its kind.
+*/
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct BaseStats {
+    pub speed: f32,
+    pub hp: f32,
+    #[serde(default)]
+    pub hp_per_level: f32,
+    pub detect_range: f32,
+    pub attack_range: f32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum MonsterSprite {
+    /// plain colored rectangle, `(r, g, b)`.
+    Color((f32, f32, f32)),
+    /// image file stretched to the monster's size.
+    Image(String),
+    /// one tile of a sprite sheet cut in `columns` by `rows` tiles of `tile_size`.
+    Atlas {
+        path: String,
+        tile_size: (f32, f32),
+        columns: usize,
+        rows: usize,
+        #[serde(default)]
+        index: usize,
+    },
+    /// clips of an [`AnimationSet`](super::animation::AnimationSet) file played by the monster's state.
+    Animated(String),
+}
+
+/// how the monster behaves out of its skill rotation.
+#[derive(Deserialize, Debug, Clone)]
+pub struct AiProfile {
+    /// how far it follows its target from its spawn point, `None` to follow it anywhere.
+    #[serde(default = "default_leash")]
+    pub leash: Option<f32>,
+    #[serde(default)]
+    pub flee: Option<Flee>,
+}
+
+fn default_leash() -> Option<f32> {
+    Some(LEASH_RADIUS)
+}
+
+impl Default for AiProfile {
+    fn default() -> Self {
+        Self {
+            leash: default_leash(),
+            flee: None,
+        }
+    }
+}
+
+/// an item dropped with a `chance` out of 1, in a stack of `min` to `max`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct LootEntry {
+    pub item: String,
+    pub chance: f32,
+    #[serde(default = "one")]
+    pub min: u32,
+    #[serde(default = "one")]
+    pub max: u32,
+}
+
+fn one() -> u32 {
+    1
+}
@@ -0,0 +1,172 @@
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::AssetServer,
+    ecs::{
+        bundle::Bundle,
+        component::Component,
+        entity::Entity,
+        query::{With, Without},
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res},
+    },
+    hierarchy::BuildChildren,
+    math::Vec2,
+    prelude::default,
+    render::color::Color,
+    sprite::{Sprite, SpriteBundle},
+    time::Time,
+    transform::components::Transform,
+};
+
+use bevy_rapier2d::control::KinematicCharacterController;
+
+use crate::{
+    game_object::system::spawn_healthbar,
+    states::{ActionState, BattleState},
+    AppState,
+};
+
+use super::{
+    animation::AnimatedSpriteBundle,
+    battle::{Dead, Stat},
+    buff::Buffs,
+    components::{GameObjectBundle, Team, TeamType},
+    physics::{move_unit, spawn_hitbox, UnitPhysicsBundle},
+    player::{Class, Player},
+    render_layer::YSort,
+    skill::SkillInfo,
+    Facing, MoveTarget, Target,
+};
+
+pub struct PetPlugin;
+
+impl Plugin for PetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (assist_owner, follow_owner).run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// distance the pet keeps from its owner while there is nothing to fight.
+const FOLLOW_DISTANCE: f32 = 80.;
+
+/// clips of the pet sprite.
+const PET_ANIMATION: &str = "pets/wolf.anim.ron";
+
+/**
+ Companion fighting for its `owner`.
+
+ a pet is a regular [`Player`] of the owner's team, so it can be selected and commanded,
+ but it attacks whatever its owner is targeting and follows it around otherwise.
+*/
+#[derive(Component)]
+pub struct Pet {
+    pub owner: Entity,
+}
+
+#[derive(Bundle)]
+pub struct PetBundle {
+    pet: Pet,
+    player: Player,
+    game_object: GameObjectBundle,
+    class: Class,
+}
+
+pub fn spawn_pet(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    owner: Entity,
+    name: String,
+    level: u32,
+    transform: Transform,
+) -> Entity {
+    let size = Vec2::new(40.0, 40.0);
+    let id = commands
+        .spawn(PetBundle {
+            pet: Pet { owner },
+            player: Player::new(name),
+            game_object: GameObjectBundle {
+                stat: Stat::new(260., 300. + 40. * level as f32, level, 3000., 80.),
+                target: Target(None),
+                sprite: SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgb(0.25, 0.25, 0.75),
+                        custom_size: Some(size),
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                },
+                move_target: MoveTarget(None),
+                action_state: ActionState::IDLE,
+                battle_state: BattleState::IDLE,
+                skill_info: SkillInfo::new(),
+                buffs: Buffs::default(),
+                facing: Facing::default(),
+                physics: UnitPhysicsBundle::new(size),
+                y_sort: YSort::feet(size.y),
+                team: Team(TeamType::PLAYER),
+            },
+            class: Class::NONE,
+        })
+        .with_children(|parent| {
+            parent.spawn(AnimatedSpriteBundle::new(asset_server, PET_ANIMATION, size));
+        })
+        .id();
+    let t = Transform::from_xyz(10000., 10000., 10000.);
+    spawn_healthbar(commands, id, t);
+    spawn_hitbox(commands, id, size);
+    id
+}
+
+/// pets switch to their owner's target, unless they were ordered to move.
+pub fn assist_owner(
+    mut pets: Query<(&Pet, &mut Target, &mut ActionState), Without<Dead>>,
+    owners: Query<&Target, Without<Pet>>,
+) {
+    for (pet, mut target, mut a_state) in &mut pets {
+        if *a_state == ActionState::MOVE {
+            continue;
+        }
+        let Ok(owner_target) = owners.get(pet.owner) else {
+            continue;
+        };
+        if owner_target.0.is_some() && target.0 != owner_target.0 {
+            *target = Target(owner_target.0);
+            *a_state = ActionState::BATTLE;
+        }
+    }
+}
+
+/// idle pets walk back to their owner.
+pub fn follow_owner(
+    time: Res<Time>,
+    mut pets: Query<
+        (
+            &Pet,
+            &Transform,
+            &Stat,
+            &ActionState,
+            &mut KinematicCharacterController,
+        ),
+        Without<Dead>,
+    >,
+    owners: Query<&Transform, (With<Player>, Without<Pet>)>,
+) {
+    for (pet, t, stat, a_state, mut controller) in &mut pets {
+        if *a_state != ActionState::IDLE {
+            continue;
+        }
+        let Ok(owner_t) = owners.get(pet.owner) else {
+            continue;
+        };
+        let diff = (owner_t.translation - t.translation).truncate();
+        if diff.length() <= FOLLOW_DISTANCE {
+            continue;
+        }
+        let step = (stat.speed * time.delta_seconds()).min(diff.length() - FOLLOW_DISTANCE);
+        move_unit(&mut controller, diff.normalize() * step);
+    }
+}
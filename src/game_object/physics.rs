@@ -0,0 +1,188 @@
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        bundle::Bundle,
+        component::Component,
+        entity::Entity,
+        query::With,
+        system::{Commands, Query, Res},
+    },
+    hierarchy::{BuildChildren, Children, Parent},
+    math::Vec2,
+    prelude::default,
+    transform::TransformBundle,
+};
+use bevy_rapier2d::{
+    control::KinematicCharacterController,
+    geometry::{
+        ActiveCollisionTypes, ActiveEvents, Collider, ColliderDisabled, CollisionGroups, Group,
+        Sensor,
+    },
+    pipeline::{QueryFilter, QueryFilterFlags},
+    plugin::{NoUserData, RapierConfiguration, RapierContext, RapierPhysicsPlugin},
+    prelude::RigidBody,
+};
+
+/// unit bodies, blocking each other.
+const BODY_GROUP: Group = Group::GROUP_1;
+/// sprite sized sensors of units, touched by projectiles and the mouse.
+const HITBOX_GROUP: Group = Group::GROUP_2;
+const PROJECTILE_GROUP: Group = Group::GROUP_3;
+
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        // top down world, nothing falls.
+        app.insert_resource(RapierConfiguration {
+            gravity: Vec2::ZERO,
+            ..default()
+        })
+        .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.));
+    }
+}
+
+/**
+ Kinematic body of a unit.
+
+ the body is a circle as wide as the sprite, so units can stand close to each other.
+ units never move their [`bevy::transform::components::Transform`] directly,
+ they queue a move with [`move_unit`] and rapier stops it against other units and obstacles.
+*/
+#[derive(Bundle)]
+pub struct UnitPhysicsBundle {
+    rigid_body: RigidBody,
+    collider: Collider,
+    collision_groups: CollisionGroups,
+    controller: KinematicCharacterController,
+}
+
+impl UnitPhysicsBundle {
+    pub fn new(size: Vec2) -> Self {
+        Self {
+            rigid_body: RigidBody::KinematicPositionBased,
+            collider: Collider::ball(size.x / 2.),
+            collision_groups: CollisionGroups::new(BODY_GROUP, Group::ALL),
+            controller: KinematicCharacterController {
+                // projectiles and zones are sensors, they don't block anybody.
+                filter_flags: QueryFilterFlags::EXCLUDE_SENSORS,
+                snap_to_ground: None,
+                autostep: None,
+                ..default()
+            },
+        }
+    }
+}
+
+/// static collider for obstacles like trees and buildings, `offset` from the entity's center.
+#[derive(Bundle)]
+pub struct ObstacleBundle {
+    rigid_body: RigidBody,
+    collider: Collider,
+}
+
+impl ObstacleBundle {
+    pub fn new(size: Vec2, offset: Vec2) -> Self {
+        Self {
+            rigid_body: RigidBody::Fixed,
+            collider: Collider::compound(vec![(
+                offset,
+                0.,
+                Collider::cuboid(size.x / 2., size.y / 2.),
+            )]),
+        }
+    }
+}
+
+/// sensor reporting the units a projectile touches through rapier collision events.
+#[derive(Bundle)]
+pub struct ProjectilePhysicsBundle {
+    rigid_body: RigidBody,
+    collider: Collider,
+    sensor: Sensor,
+    collision_groups: CollisionGroups,
+    active_events: ActiveEvents,
+    active_collision_types: ActiveCollisionTypes,
+}
+
+impl ProjectilePhysicsBundle {
+    pub fn new(size: Vec2) -> Self {
+        Self {
+            rigid_body: RigidBody::KinematicPositionBased,
+            collider: Collider::cuboid(size.x / 2., size.y / 2.),
+            sensor: Sensor,
+            collision_groups: CollisionGroups::new(PROJECTILE_GROUP, HITBOX_GROUP),
+            active_events: ActiveEvents::COLLISION_EVENTS,
+            // kinematic projectiles against kinematic units aren't checked by default.
+            active_collision_types: ActiveCollisionTypes::all(),
+        }
+    }
+}
+
+/// queues a move of the unit, resolved against obstacles on the next physics step.
+pub fn move_unit(controller: &mut KinematicCharacterController, delta: Vec2) {
+    controller.translation = Some(controller.translation.unwrap_or(Vec2::ZERO) + delta);
+}
+
+/// sprite sized sensor child of a unit, see [`spawn_hitbox`].
+#[derive(Component)]
+pub struct Hitbox;
+
+/// attaches a [`Hitbox`] covering the unit's sprite, used for projectile hits and selection.
+pub fn spawn_hitbox(commands: &mut Commands, id: Entity, size: Vec2) {
+    commands.entity(id).with_children(|parent| {
+        parent.spawn((
+            Hitbox,
+            TransformBundle::default(),
+            Collider::cuboid(size.x / 2., size.y / 2.),
+            Sensor,
+            CollisionGroups::new(HITBOX_GROUP, PROJECTILE_GROUP),
+        ));
+    });
+}
+
+/// turns the [`Hitbox`] among `children` off, so a corpse is neither hit nor picked, or back on.
+pub fn enable_hitbox(
+    commands: &mut Commands,
+    children: &Children,
+    hitboxes: &Query<Entity, With<Hitbox>>,
+    enabled: bool,
+) {
+    for hitbox in hitboxes.iter_many(children) {
+        if enabled {
+            commands.entity(hitbox).remove::<ColliderDisabled>();
+        } else {
+            commands.entity(hitbox).insert(ColliderDisabled);
+        }
+    }
+}
+
+/// units whose [`Hitbox`] contains the point.
+pub fn units_at(
+    rapier_context: &Res<RapierContext>,
+    hitboxes: &Query<&Parent, With<Hitbox>>,
+    point: Vec2,
+) -> Vec<Entity> {
+    let mut units = Vec::new();
+    let filter = QueryFilter::new().groups(CollisionGroups::new(Group::ALL, HITBOX_GROUP));
+    rapier_context.intersections_with_point(point, filter, |collider| {
+        if let Ok(parent) = hitboxes.get(collider) {
+            units.push(parent.get());
+        }
+        true
+    });
+    units
+}
+
+/// whether a box of `size` centered on `center` touches an obstacle or the body of a unit.
+pub fn blocked_at(rapier_context: &Res<RapierContext>, center: Vec2, size: Vec2) -> bool {
+    let mut blocked = false;
+    // hitboxes and projectiles are sensors, they don't take any room.
+    let filter = QueryFilter::new().exclude_sensors();
+    let shape = Collider::cuboid(size.x / 2., size.y / 2.);
+    rapier_context.intersections_with_shape(center, 0., &shape, filter, |_| {
+        blocked = true;
+        false
+    });
+    blocked
+}
@@ -1,5 +1,6 @@
 use bevy::{
     app::{App, FixedUpdate, Plugin},
+    asset::AssetServer,
     ecs::{
         bundle::Bundle,
         component::Component,
@@ -8,6 +9,7 @@ use bevy::{
         schedule::{common_conditions::in_state, IntoSystemConfigs},
         system::{Commands, Query, Res},
     },
+    hierarchy::BuildChildren,
     math::{Vec2, Vec3},
     prelude::default,
     render::color::Color,
@@ -17,17 +19,24 @@ use bevy::{
 };
 
 use crate::{
-    game_object::system::{spawn_castingbar, spawn_healthbar},
+    game_object::system::{spawn_castingbar, spawn_healthbar, spawn_powerbar},
     states::{ActionState, BattleState},
     AppState,
 };
 
 use super::{
-    battle::Stat,
+    animation::AnimatedSpriteBundle,
+    battle::{ComboPoints, Dead, Exp, Power, Stat, MAX_COMBO_POINTS},
+    buff::{BuffKind, Buffs},
+    building::Building,
     components::{GameObjectBundle, Team, TeamType},
     monster::Monster,
-    skill::SkillInfo,
-    MoveTarget, Target,
+    pet::spawn_pet,
+    physics::{spawn_hitbox, UnitPhysicsBundle},
+    render_layer::YSort,
+    rotation::{Condition, Rotation, RotationEntry},
+    skill::{SkillCode, SkillInfo},
+    Facing, MoveTarget, Target,
 };
 
 pub struct PlayerPlugin;
@@ -51,35 +60,191 @@ pub enum Class {
     HUNTER,
 }
 
+impl Class {
+    /// skill used whenever nothing else is queued.
+    pub fn auto_attack(&self) -> SkillCode {
+        match self {
+            Class::NONE => SkillCode::BaseAttack,
+            Class::KNIGHT => SkillCode::Slash,
+            Class::MAGE => SkillCode::MagicBolt,
+            Class::PRIEST => SkillCode::Smite,
+            Class::ROGUE => SkillCode::BaseAttack,
+            Class::HUNTER => SkillCode::Shot,
+        }
+    }
+
+    /// clips of the class sprite, a player without a class stays a plain rectangle.
+    pub fn animation(&self) -> Option<&'static str> {
+        match self {
+            Class::NONE => None,
+            Class::KNIGHT => Some("players/knight.anim.ron"),
+            Class::MAGE => Some("players/mage.anim.ron"),
+            Class::PRIEST => Some("players/priest.anim.ron"),
+            Class::ROGUE => Some("players/rogue.anim.ron"),
+            Class::HUNTER => Some("players/hunter.anim.ron"),
+        }
+    }
+
+    /// skill priority used while the player is fighting on its own.
+    pub fn rotation(&self) -> Rotation {
+        match self {
+            Class::KNIGHT => Rotation(vec![
+                RotationEntry::new(
+                    SkillCode::Charge,
+                    vec![Condition::TargetDistanceAbove(150.)],
+                ),
+                RotationEntry::new(
+                    SkillCode::Taunt,
+                    vec![Condition::TargetBuffMissing(BuffKind::Taunted)],
+                ),
+                RotationEntry::new(
+                    SkillCode::ShieldBlock,
+                    vec![Condition::BuffMissing(BuffKind::ShieldBlock)],
+                ),
+                RotationEntry::new(
+                    SkillCode::Consecration,
+                    vec![Condition::EnemiesInRange {
+                        count: 2,
+                        range: 150.,
+                    }],
+                ),
+            ]),
+            Class::PRIEST => Rotation(vec![
+                RotationEntry::new(SkillCode::Resurrect, vec![]),
+                RotationEntry::new(
+                    SkillCode::PrayerOfHealing,
+                    vec![Condition::AlliesBelow {
+                        count: 3,
+                        ratio: 0.7,
+                    }],
+                ),
+                RotationEntry::new(
+                    SkillCode::Sanctuary,
+                    vec![Condition::AlliesBelow {
+                        count: 2,
+                        ratio: 0.8,
+                    }],
+                ),
+                RotationEntry::new(SkillCode::Heal, vec![Condition::TargetHealthBelow(0.5)]),
+                RotationEntry::new(
+                    SkillCode::Renew,
+                    vec![
+                        Condition::TargetHealthBelow(0.9),
+                        Condition::TargetBuffMissing(BuffKind::Renew),
+                    ],
+                ),
+            ]),
+            Class::ROGUE => Rotation(vec![
+                RotationEntry::new(
+                    SkillCode::Stealth,
+                    vec![
+                        Condition::BuffMissing(BuffKind::Stealth),
+                        Condition::TargetDistanceAbove(400.),
+                    ],
+                ),
+                RotationEntry::new(SkillCode::Kick, vec![Condition::TargetCasting]),
+                RotationEntry::new(
+                    SkillCode::Eviscerate,
+                    vec![Condition::ComboPointsAtLeast(MAX_COMBO_POINTS)],
+                ),
+                RotationEntry::new(SkillCode::Backstab, vec![Condition::BehindTarget]),
+                RotationEntry::new(SkillCode::SinisterStrike, vec![]),
+            ]),
+            Class::HUNTER => Rotation(vec![RotationEntry::new(
+                SkillCode::AimedShot,
+                vec![Condition::TargetDistanceAbove(250.)],
+            )]),
+            Class::MAGE => Rotation(vec![
+                RotationEntry::new(
+                    SkillCode::ArcanePower,
+                    vec![Condition::BuffMissing(BuffKind::ArcanePower)],
+                ),
+                RotationEntry::new(
+                    SkillCode::ArcaneExplosion,
+                    vec![Condition::EnemiesInRange {
+                        count: 2,
+                        range: 150.,
+                    }],
+                ),
+                RotationEntry::new(SkillCode::Blizzard, vec![]),
+                RotationEntry::new(SkillCode::FireBall, vec![]),
+                RotationEntry::new(SkillCode::ArcaneMissiles, vec![]),
+            ]),
+            _ => Rotation::default(),
+        }
+    }
+
+    /// base stats of the class at the given level.
+    pub fn base_stat(&self, level: u32) -> Stat {
+        let level_f = level as f32;
+        match self {
+            Class::NONE => Stat::new(200., 400. + 40. * level_f, level, 3000., 80.),
+            Class::KNIGHT => Stat::new(220., 900. + 90. * level_f, level, 3000., 80.),
+            Class::MAGE => Stat::new(200., 500. + 40. * level_f, level, 3000., 200.),
+            Class::PRIEST => Stat::new(200., 450. + 35. * level_f, level, 3000., 200.),
+            Class::ROGUE => Stat::new(240., 600. + 55. * level_f, level, 3000., 80.),
+            Class::HUNTER => Stat::new(220., 550. + 45. * level_f, level, 3000., 600.),
+        }
+    }
+
+    pub fn power(&self) -> Option<Power> {
+        match self {
+            Class::NONE => None,
+            Class::MAGE | Class::PRIEST => Some(Power::mana(300., 6.)),
+            Class::KNIGHT => Some(Power::rage()),
+            Class::ROGUE | Class::HUNTER => Some(Power::energy()),
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct Player {
     name: String,
 }
 
+impl Player {
+    pub fn new(name: String) -> Self {
+        Self { name }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
 #[derive(Bundle)]
 pub struct PlayerBundle {
     player: Player,
     game_object: GameObjectBundle,
     class: Class,
+    rotation: Rotation,
 }
 
 pub fn spawn_player(
     commands: &mut Commands,
+    asset_server: &AssetServer,
     name: String,
     stat: Stat,
     class: Class,
     transform: Transform,
 ) -> Entity {
+    let power = class.power();
+    let rotation = class.rotation();
+    let is_rogue = matches!(class, Class::ROGUE);
+    let is_hunter = matches!(class, Class::HUNTER);
+    let level = stat.level;
+    let size = Vec2::new(50.0, 100.0);
+    let animation = class.animation();
     let id = commands
         .spawn(PlayerBundle {
-            player: Player { name },
+            player: Player::new(name.clone()),
             game_object: GameObjectBundle {
                 stat,
                 target: Target(None),
                 sprite: SpriteBundle {
                     sprite: Sprite {
                         color: Color::rgb(0.25, 0.25, 0.75),
-                        custom_size: Some(Vec2::new(50.0, 100.0)),
+                        custom_size: Some(size),
                         ..default()
                     },
                     transform,
@@ -89,13 +254,44 @@ pub fn spawn_player(
                 action_state: ActionState::IDLE,
                 battle_state: BattleState::IDLE,
                 skill_info: SkillInfo::new(),
+                buffs: Buffs::default(),
+                facing: Facing::default(),
+                physics: UnitPhysicsBundle::new(size),
+                y_sort: YSort::feet(size.y),
                 team: Team(TeamType::PLAYER),
             },
             class,
+            rotation,
         })
         .id();
+    if let Some(path) = animation {
+        // the sheet is drawn by a child, the unit keeps an invisible sprite for its size.
+        commands.entity(id).with_children(|parent| {
+            parent.spawn(AnimatedSpriteBundle::new(asset_server, path, size));
+        });
+    }
     let t = Transform::from_xyz(10000., 10000., 10000.);
     spawn_healthbar(commands, id, t);
+    spawn_hitbox(commands, id, size);
+    commands.entity(id).insert(Exp::new(level));
+    if let Some(power) = power {
+        spawn_powerbar(commands, id, power.power_type, t);
+        commands.entity(id).insert(power);
+    }
+    if is_rogue {
+        commands.entity(id).insert(ComboPoints::default());
+    }
+    if is_hunter {
+        let pet_t = transform.with_translation(transform.translation + Vec3::new(-60., 0., 0.));
+        spawn_pet(
+            commands,
+            asset_server,
+            id,
+            format!("{}'s pet", name),
+            level,
+            pet_t,
+        );
+    }
     spawn_castingbar(commands, id, t);
     id
 }
@@ -112,9 +308,10 @@ pub fn trig_player_action(
             &mut BattleState,
             &mut SkillInfo,
         ),
-        (With<Player>, Without<Monster>),
+        (With<Player>, Without<Monster>, Without<Dead>),
     >,
     monsters: Query<&mut Transform, With<Monster>>,
+    buildings: Query<&Transform, (With<Building>, Without<Player>, Without<Monster>)>,
 ) {
     for (p_transform, p_stat, p_target, mut a_state, mut b_state, mut skill_info) in &mut player {
         //info!(
@@ -126,7 +323,7 @@ pub fn trig_player_action(
                 // if target exists.
                 Some(ent) => {
                     // if get monster entity successfully.
-                    match monsters.get(ent).ok() {
+                    match monsters.get(ent).or_else(|_| buildings.get(ent)).ok() {
                         Some(m_t) => {
                             let dist = p_transform.translation.distance(m_t.translation);
                             // info!("dist : {:?}", dist);
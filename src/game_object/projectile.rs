@@ -1,29 +1,38 @@
 use bevy::{
-    app::{App, FixedUpdate, Plugin, Update},
+    app::{App, Plugin, Update},
     ecs::{
         bundle::Bundle,
         component::Component,
         entity::Entity,
-        event::EventWriter,
+        event::{EventReader, EventWriter},
         query::{Or, With, Without},
         schedule::{common_conditions::in_state, IntoSystemConfigs},
         system::{Commands, Query, Res},
     },
+    hierarchy::Parent,
     log::info,
     math::{Vec2, Vec3},
     prelude::default,
     render::{camera::Camera, color::Color},
-    sprite::{collide_aabb::collide, Sprite, SpriteBundle},
-    time::Time,
+    sprite::{Sprite, SpriteBundle},
+    time::{Time, Timer, TimerMode},
     transform::components::Transform,
 };
+use bevy_rapier2d::pipeline::CollisionEvent;
+use serde::Deserialize;
 
 use crate::AppState;
 
 use super::{
-    battle::{Damage, DamageType, Heal, HealType, Stat},
+    battle::Dead,
+    building::Building,
+    components::{Team, TeamType},
     monster::Monster,
+    physics::{Hitbox, ProjectilePhysicsBundle},
     player::Player,
+    render_layer::YSort,
+    skill::{SkillCode, SkillHit},
+    skill_definition::Relation,
     Target,
 };
 
@@ -32,19 +41,18 @@ pub struct ProjectilePlugin;
 impl Plugin for ProjectilePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
-            FixedUpdate,
-            check_collisions.run_if(in_state(AppState::InGame)),
-        )
-        .add_systems(
             Update,
-            (move_projectile, clear_projectile).run_if(in_state(AppState::InGame)),
+            (move_projectile, projectile_hits, clear_projectile).run_if(in_state(AppState::InGame)),
         );
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
 pub enum ProjectileType {
+    /// homes toward its [`Target`] and only hits it.
+    #[default]
     Targeting,
+    /// flies straight in its direction and hits the first hostile units it touches.
     NonTargeting,
 }
 
@@ -53,26 +61,31 @@ pub struct ProjectileBundle {
     projectile: Projectile,
     sprite: SpriteBundle,
     target: Target,
+    physics: ProjectilePhysicsBundle,
 }
 
 #[derive(Component, Debug)]
 pub struct Projectile {
     projectile_type: ProjectileType,
     owner: Entity,
+    team: TeamType,
     speed: f32,
-    damage: f32,
-    damage_type: Option<DamageType>,
-    heal_type: Option<HealType>,
-    lifetime: f32,
+    skill: SkillCode,
+    level: u32,
+    lifetime: Timer,
+    direction: Vec2,
+    /// how many units a [`ProjectileType::NonTargeting`] projectile goes through before vanishing.
+    pierce: u32,
+    hits: Vec<Entity>,
 }
 
 impl Projectile {
     pub fn new(
         speed: f32,
-        damage: f32,
         owner: Entity,
-        damage_type: Option<DamageType>,
-        heal_type: Option<HealType>,
+        team: TeamType,
+        skill: SkillCode,
+        level: u32,
         lifetime: f32,
         projectile_type: ProjectileType,
     ) -> Self {
@@ -80,10 +93,49 @@ impl Projectile {
             projectile_type,
             speed,
             owner,
-            damage,
-            damage_type,
-            heal_type,
-            lifetime,
+            team,
+            skill,
+            level,
+            lifetime: Timer::from_seconds(lifetime, TimerMode::Once),
+            direction: Vec2::X,
+            pierce: 0,
+            hits: Vec::new(),
+        }
+    }
+
+    pub fn with_direction(mut self, direction: Vec2) -> Self {
+        self.direction = direction.normalize_or_zero();
+        self
+    }
+
+    pub fn with_pierce(mut self, pierce: u32) -> Self {
+        self.pierce = pierce;
+        self
+    }
+
+    fn hit(&self, target: Entity) -> SkillHit {
+        SkillHit {
+            caster: self.owner,
+            target,
+            skill: self.skill,
+            level: self.level,
+        }
+    }
+
+    fn is_spent(&self) -> bool {
+        match self.projectile_type {
+            ProjectileType::Targeting => !self.hits.is_empty(),
+            ProjectileType::NonTargeting => self.hits.len() as u32 > self.pierce,
+        }
+    }
+
+    /// whether the projectile can hit the unit of the given team.
+    fn can_hit(&self, ent: Entity, team: &TeamType, target: &Target) -> bool {
+        match self.projectile_type {
+            ProjectileType::Targeting => target.0 == Some(ent),
+            ProjectileType::NonTargeting => {
+                Relation::Hostile.matches(&self.team, team) && !self.hits.contains(&ent)
+            }
         }
     }
 }
@@ -91,165 +143,145 @@ impl Projectile {
 pub fn spawn_projectile(
     command: &mut Commands,
     projectile: Projectile,
+    size: Vec2,
+    color: Color,
     transform: Transform,
     target: Target,
 ) {
-    command.spawn(ProjectileBundle {
-        projectile,
-        target,
-        sprite: SpriteBundle {
-            sprite: Sprite {
-                color: Color::ORANGE,
-                custom_size: Some(Vec2::new(10.0, 10.0)),
+    command.spawn((
+        ProjectileBundle {
+            projectile,
+            target,
+            physics: ProjectilePhysicsBundle::new(size),
+            sprite: SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(size),
+                    ..default()
+                },
+                transform,
                 ..default()
             },
-            transform,
-            ..default()
         },
-    });
+        YSort::default(),
+    ));
 }
 
 //systems
+/**
+ moves projectiles and despawns them once their lifetime is over.
+
+ targeting projectiles home toward their target, the others fly in a straight line.
+*/
 pub fn move_projectile(
     mut command: Commands,
     time: Res<Time>,
     mut projectiles: Query<
-        (Entity, &mut Transform, &Projectile, &Target),
+        (Entity, &mut Transform, &mut Projectile, &Target),
         (With<Projectile>, Without<Monster>, Without<Player>),
     >,
     entities: Query<
         &Transform,
         (
-            Or<(With<Monster>, With<Player>)>,
+            Or<(With<Monster>, With<Player>, With<Building>)>,
             Without<Camera>,
             Without<Projectile>,
         ),
     >,
 ) {
-    for (ent, mut t, proj, target) in &mut projectiles {
+    for (ent, mut t, mut proj, target) in &mut projectiles {
         // info!("proj : {:?}", proj);
-        match target.0 {
-            Some(ent) => {
-                if let Some(ent_t) = entities.get(ent).ok() {
-                    let ent_t = ent_t.translation;
-                    let mut t_temp = t.translation;
-                    let dir = Vec3::new(ent_t.x - t_temp.x, ent_t.y - t_temp.y, 0.)
-                        .normalize_or_zero()
-                        * proj.speed
-                        * time.delta_seconds();
-                    t.translation += dir;
+        proj.lifetime.tick(time.delta());
+        if proj.lifetime.finished() {
+            command.entity(ent).despawn();
+            continue;
+        }
+        match proj.projectile_type {
+            ProjectileType::Targeting => match target.0 {
+                Some(ent) => {
+                    if let Some(ent_t) = entities.get(ent).ok() {
+                        let ent_t = ent_t.translation;
+                        let mut t_temp = t.translation;
+                        let dir = Vec3::new(ent_t.x - t_temp.x, ent_t.y - t_temp.y, 0.)
+                            .normalize_or_zero()
+                            * proj.speed
+                            * time.delta_seconds();
+                        t.translation += dir;
+                    }
                 }
-            }
-            None => {
-                command.entity(ent).despawn();
-                continue;
+                None => {
+                    command.entity(ent).despawn();
+                    continue;
+                }
+            },
+            ProjectileType::NonTargeting => {
+                t.translation += (proj.direction * proj.speed * time.delta_seconds()).extend(0.);
             }
         }
     }
 }
 
-pub fn check_collisions(
+/**
+ turns rapier collision events between projectiles and unit [`Hitbox`]es into [`SkillHit`]s.
+
+ a projectile is spent after its hit, or once it went through `pierce` extra units.
+*/
+pub fn projectile_hits(
     mut command: Commands,
-    projectiles: Query<
-        (Entity, &Transform, &Sprite, &Projectile, &Target),
-        (With<Projectile>, Without<Player>, Without<Monster>),
-    >,
-    mut monsters: Query<
-        (Entity, &Transform, &Sprite, &mut Stat),
-        (With<Monster>, Without<Player>, Without<Projectile>),
-    >,
-    mut players: Query<
-        (Entity, &Transform, &Sprite, &mut Stat),
-        (With<Player>, Without<Monster>, Without<Projectile>),
+    mut collision_evt: EventReader<CollisionEvent>,
+    mut projectiles: Query<(&mut Projectile, &Target)>,
+    hitboxes: Query<&Parent, With<Hitbox>>,
+    units: Query<
+        &Team,
+        (
+            Or<(With<Monster>, With<Player>, With<Building>)>,
+            Without<Dead>,
+        ),
     >,
-    mut damage_evt: EventWriter<Damage>,
-    mut heal_evt: EventWriter<Heal>,
+    mut hit_evt: EventWriter<SkillHit>,
 ) {
-    for (p_ent, p_t, p_sprite, projectile, target) in &projectiles {
-        let Some(targ) = target.0 else {
+    for evt in collision_evt.read() {
+        let CollisionEvent::Started(a, b, _) = evt else {
             continue;
         };
-        if monsters.contains(targ) {
-            for (ent, ent_t, ent_sprite, mut ent_stat) in &mut monsters {
-                if projectile.projectile_type == ProjectileType::Targeting && target.0 != Some(ent)
-                {
-                    continue;
-                }
-                let Some(p_size) = p_sprite.custom_size else {
-                    continue;
-                };
-                let Some(ent_size) = ent_sprite.custom_size else {
-                    continue;
-                };
-
-                if collide(p_t.translation, p_size, ent_t.translation, ent_size).is_some() {
-                    if projectile.damage_type.is_some() {
-                        damage_evt.send(Damage {
-                            attacker: projectile.owner,
-                            damage: projectile.damage,
-                            damage_type: projectile.damage_type.clone().unwrap(),
-                            attacked: ent,
-                        })
-                    }
-                    if projectile.heal_type.is_some() {
-                        heal_evt.send(Heal {
-                            healer: projectile.owner,
-                            value: projectile.damage,
-                            heal_type: projectile.heal_type.clone().unwrap(),
-                            healed: ent,
-                        })
-                    }
-                    // info!(
-                    //     "current : {:?}, damage: {:?}",
-                    //     ent_stat.hp.current, projectile.damage
-                    // );
-                    command.entity(p_ent).despawn();
-                }
-            }
+        let (p_ent, other) = if projectiles.contains(*a) {
+            (*a, *b)
+        } else if projectiles.contains(*b) {
+            (*b, *a)
+        } else {
+            continue;
+        };
+        let Ok(ent) = hitboxes.get(other).map(|parent| parent.get()) else {
+            continue;
+        };
+        let Ok(team) = units.get(ent) else {
+            continue;
+        };
+        let Ok((mut projectile, target)) = projectiles.get_mut(p_ent) else {
+            continue;
+        };
+        // a projectile despawned this frame can still report other collisions.
+        if projectile.is_spent() || !projectile.can_hit(ent, &team.0, target) {
+            continue;
         }
 
-        if players.contains(targ) {
-            for (ent, ent_t, ent_sprite, mut ent_stat) in &mut players {
-                if projectile.projectile_type == ProjectileType::Targeting && target.0 != Some(ent)
-                {
-                    continue;
-                }
-                let Some(p_size) = p_sprite.custom_size else {
-                    continue;
-                };
-                let Some(ent_size) = ent_sprite.custom_size else {
-                    continue;
-                };
-
-                if collide(p_t.translation, p_size, ent_t.translation, ent_size).is_some() {
-                    if projectile.damage_type.is_some() {
-                        damage_evt.send(Damage {
-                            attacker: projectile.owner,
-                            damage: projectile.damage,
-                            damage_type: projectile.damage_type.clone().unwrap(),
-                            attacked: ent,
-                        })
-                    }
-                    if projectile.heal_type.is_some() {
-                        heal_evt.send(Heal {
-                            healer: projectile.owner,
-                            value: projectile.damage,
-                            heal_type: projectile.heal_type.clone().unwrap(),
-                            healed: ent,
-                        })
-                    }
-                    command.entity(p_ent).despawn();
-                }
-            }
+        hit_evt.send(projectile.hit(ent));
+        projectile.hits.push(ent);
+        if projectile.is_spent() {
+            command.entity(p_ent).despawn();
         }
     }
 }
 
+/// despawns targeting projectiles whose target is gone.
 pub fn clear_projectile(
     mut commands: Commands,
-    projectiles: Query<(Entity, &Target), With<Projectile>>,
+    projectiles: Query<(Entity, &Projectile, &Target)>,
 ) {
-    for (ent, target) in &projectiles {
+    for (ent, projectile, target) in &projectiles {
+        if projectile.projectile_type != ProjectileType::Targeting {
+            continue;
+        }
         let Some(tar) = target.0 else {
             commands.entity(ent).despawn();
             continue;
@@ -0,0 +1,90 @@
+use bevy::{
+    app::{App, Plugin, PostUpdate},
+    ecs::{
+        component::Component,
+        query::{Added, Changed, Or},
+        schedule::IntoSystemConfigs,
+        system::Query,
+    },
+    transform::{components::Transform, TransformSystem},
+};
+
+pub struct RenderLayerPlugin;
+
+impl Plugin for RenderLayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            y_sort.before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/**
+ Named bands of z the world is drawn in, from the back to the front.
+
+ the 2d camera only sees z between 0 and its far plane at 1000, every band lies in there.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderLayer {
+    /// the baked tile chunks.
+    Ground,
+    /// things lying on the ground, like ground effects.
+    Decals,
+    /// units, projectiles and environment objects, sorted by their height on the screen.
+    Units,
+    /// health, power and casting bars, over every unit.
+    Overhead,
+    /// damage numbers, over everything.
+    Popups,
+}
+
+impl RenderLayer {
+    pub const fn z(self) -> f32 {
+        match self {
+            RenderLayer::Ground => 1.,
+            RenderLayer::Decals => 10.,
+            RenderLayer::Units => 100.,
+            RenderLayer::Overhead => 600.,
+            RenderLayer::Popups => 800.,
+        }
+    }
+}
+
+/// depth of the units band, between [`RenderLayer::Units`] and [`RenderLayer::Overhead`].
+const UNITS_DEPTH: f32 = 400.;
+/// z per world unit of height in the units band, enough for 40000 units from top to bottom.
+const Y_SORT_SCALE: f32 = 0.01;
+
+/// z in the units band of something standing at height `y`, lower meaning closer.
+pub fn y_sorted_z(y: f32) -> f32 {
+    RenderLayer::Units.z() + (UNITS_DEPTH / 2. - y * Y_SORT_SCALE).clamp(0., UNITS_DEPTH)
+}
+
+/// puts an entity in the units band, drawn over everything standing higher on the screen.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct YSort {
+    /// from the position to the point that is sorted, like the feet or the trunk.
+    pub offset: f32,
+}
+
+impl YSort {
+    /// sorted by the bottom of a centered sprite of `height`.
+    pub fn feet(height: f32) -> Self {
+        Self {
+            offset: -height / 2.,
+        }
+    }
+}
+
+/// sorts what moved, and what just got its [`YSort`] even when it stands still.
+pub fn y_sort(
+    mut entities: Query<(&YSort, &mut Transform), Or<(Changed<Transform>, Added<YSort>)>>,
+) {
+    for (sort, mut transform) in &mut entities {
+        let z = y_sorted_z(transform.translation.y + sort.offset);
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
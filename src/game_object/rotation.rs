@@ -0,0 +1,102 @@
+use bevy::ecs::component::Component;
+use serde::Deserialize;
+
+use super::{
+    buff::{BuffKind, Buffs},
+    skill::SkillCode,
+};
+
+/**
+ Priority list of skills an AI controlled unit goes through every tick.
+
+ the first entry whose skill is ready, affordable and whose [`Condition`]s all pass is used,
+ the auto attack being the fallback when nothing matches.
+*/
+#[derive(Component, Deserialize, Clone, Debug, Default)]
+pub struct Rotation(pub Vec<RotationEntry>);
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RotationEntry {
+    pub skill: SkillCode,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+impl RotationEntry {
+    pub fn new(skill: SkillCode, conditions: Vec<Condition>) -> Self {
+        Self { skill, conditions }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum Condition {
+    /// target health ratio is below the value, `0.3` for 30%.
+    TargetHealthBelow(f32),
+    /// the caster doesn't have the buff.
+    BuffMissing(BuffKind),
+    /// the target doesn't have the buff.
+    TargetBuffMissing(BuffKind),
+    /// the target is further away than the value.
+    TargetDistanceAbove(f32),
+    /// at least `count` living allies have their health ratio below `ratio`.
+    AlliesBelow { count: usize, ratio: f32 },
+    /// at least `count` hostile units within `range` of the caster.
+    EnemiesInRange { count: usize, range: f32 },
+    /// the caster has at least this many combo points.
+    ComboPointsAtLeast(u32),
+    /// the caster stands behind its target.
+    BehindTarget,
+    /// the target is casting or channelling a skill.
+    TargetCasting,
+}
+
+/// what a [`Condition`] is checked against.
+pub struct RotationContext<'a> {
+    pub caster_buffs: Option<&'a Buffs>,
+    pub target_distance: Option<f32>,
+    pub target_health_ratio: Option<f32>,
+    pub target_buffs: Option<&'a Buffs>,
+    /// distance from the caster to every hostile unit.
+    pub enemy_distances: &'a [f32],
+    /// health ratio of every living ally, the caster included.
+    pub ally_health_ratios: &'a [f32],
+    pub combo_points: u32,
+    pub behind_target: bool,
+    pub target_casting: bool,
+}
+
+impl Condition {
+    pub fn check(&self, context: &RotationContext) -> bool {
+        match self {
+            Condition::TargetHealthBelow(ratio) => context
+                .target_health_ratio
+                .is_some_and(|target_ratio| target_ratio < *ratio),
+            Condition::TargetDistanceAbove(distance) => context
+                .target_distance
+                .is_some_and(|target_distance| target_distance > *distance),
+            Condition::BuffMissing(kind) => !context.caster_buffs.is_some_and(|b| b.has(*kind)),
+            Condition::TargetBuffMissing(kind) => {
+                !context.target_buffs.is_some_and(|b| b.has(*kind))
+            }
+            Condition::AlliesBelow { count, ratio } => {
+                context
+                    .ally_health_ratios
+                    .iter()
+                    .filter(|ally_ratio| **ally_ratio < *ratio)
+                    .count()
+                    >= *count
+            }
+            Condition::EnemiesInRange { count, range } => {
+                context
+                    .enemy_distances
+                    .iter()
+                    .filter(|distance| **distance <= *range)
+                    .count()
+                    >= *count
+            }
+            Condition::ComboPointsAtLeast(points) => context.combo_points >= *points,
+            Condition::BehindTarget => context.behind_target,
+            Condition::TargetCasting => context.target_casting,
+        }
+    }
+}
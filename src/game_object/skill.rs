@@ -1,68 +1,283 @@
 use super::{
-    battle::{Attacked, DamageType, Stat},
+    animation::{AnimationFrameEvent, RELEASE_EVENT},
+    battle::{Attacked, ComboPoints, Damage, Dead, Heal, HealType, Power, Stat},
+    buff::{BuffKind, Buffs},
+    building::Building,
     components::{Team, TeamType},
+    ground_effect::SpawnGroundEffect,
     monster::Monster,
     player::{Class, Player},
     projectile::{spawn_projectile, Projectile, ProjectileType},
-    Target,
+    rotation::{Rotation, RotationContext},
+    skill_definition::{scaled, SkillDefinition, SkillEffect, SpellSchool, TargetingMode},
+    Charging, Facing, Target,
 };
 use crate::{
+    data::RonLoader,
     states::{ActionState, BattleState},
     AppState,
 };
 use bevy::{
-    app::{App, FixedUpdate, Plugin, Update},
+    app::{App, FixedUpdate, Plugin, Startup, Update},
+    asset::{AssetApp, AssetServer, Assets, Handle},
     ecs::{
         component::Component,
         entity::Entity,
-        event::EventWriter,
-        query::{Or, With},
+        event::{Event, EventReader, EventWriter},
+        query::{Has, Or, With, Without},
         schedule::{common_conditions::in_state, IntoSystemConfigs},
-        system::{Commands, Query, Res},
+        system::{Commands, Query, Res, ResMut, Resource},
     },
-    time::{Stopwatch, Time},
+    log::info,
+    math::Vec2,
+    time::{Stopwatch, Time, Timer, TimerMode},
     transform::components::Transform,
+    utils::{HashMap, HashSet},
 };
+use serde::Deserialize;
 use std::time::Duration;
 
 pub struct SkillPlugin;
 
 impl Plugin for SkillPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            FixedUpdate,
-            (use_skill, base_attack).run_if(in_state(AppState::InGame)),
-        );
+        app.init_asset::<SkillDefinition>()
+            .register_asset_loader(RonLoader::<SkillDefinition>::new(&["skill.ron"]))
+            .init_resource::<SkillBook>()
+            .add_event::<SkillHit>()
+            .add_event::<Interrupted>()
+            .add_event::<SkillReleased>()
+            .add_event::<ChargeArrived>()
+            .add_systems(Startup, load_skill_book)
+            .add_systems(
+                FixedUpdate,
+                (tick_cooldowns, use_skill, cast_skill)
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                Update,
+                (
+                    apply_skill_hit,
+                    pushback_casts,
+                    release_on_frame,
+                    announce_interrupts,
+                )
+                    .run_if(in_state(AppState::InGame)),
+            );
     }
 }
 
-#[derive(PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Deserialize)]
 pub enum SkillCode {
     BaseAttack,
+    MagicBolt,
     FireBall,
+    ArcanePower,
+    ArcaneExplosion,
+    Slash,
+    Taunt,
+    ShieldBlock,
+    Charge,
+    Smite,
+    Heal,
+    Renew,
+    PrayerOfHealing,
+    Resurrect,
+    Stealth,
+    SinisterStrike,
+    Backstab,
+    Eviscerate,
+    Shot,
+    AimedShot,
+    Consecration,
+    Blizzard,
+    Sanctuary,
+    Kick,
+    ArcaneMissiles,
 }
 
-#[derive(Component)]
-pub struct Skill {
-    id: SkillCode,
-    name: String,
+impl SkillCode {
+    pub const ALL: [SkillCode; 25] = [
+        SkillCode::BaseAttack,
+        SkillCode::MagicBolt,
+        SkillCode::FireBall,
+        SkillCode::ArcanePower,
+        SkillCode::ArcaneExplosion,
+        SkillCode::Slash,
+        SkillCode::Taunt,
+        SkillCode::ShieldBlock,
+        SkillCode::Charge,
+        SkillCode::Smite,
+        SkillCode::Heal,
+        SkillCode::Renew,
+        SkillCode::PrayerOfHealing,
+        SkillCode::Resurrect,
+        SkillCode::Stealth,
+        SkillCode::SinisterStrike,
+        SkillCode::Backstab,
+        SkillCode::Eviscerate,
+        SkillCode::Shot,
+        SkillCode::AimedShot,
+        SkillCode::Consecration,
+        SkillCode::Blizzard,
+        SkillCode::Sanctuary,
+        SkillCode::Kick,
+        SkillCode::ArcaneMissiles,
+    ];
+
+    pub fn asset_path(&self) -> &'static str {
+        match self {
+            SkillCode::BaseAttack => "skills/base_attack.skill.ron",
+            SkillCode::MagicBolt => "skills/magic_bolt.skill.ron",
+            SkillCode::FireBall => "skills/fire_ball.skill.ron",
+            SkillCode::ArcanePower => "skills/arcane_power.skill.ron",
+            SkillCode::ArcaneExplosion => "skills/arcane_explosion.skill.ron",
+            SkillCode::Slash => "skills/slash.skill.ron",
+            SkillCode::Taunt => "skills/taunt.skill.ron",
+            SkillCode::ShieldBlock => "skills/shield_block.skill.ron",
+            SkillCode::Charge => "skills/charge.skill.ron",
+            SkillCode::Smite => "skills/smite.skill.ron",
+            SkillCode::Heal => "skills/heal.skill.ron",
+            SkillCode::Renew => "skills/renew.skill.ron",
+            SkillCode::PrayerOfHealing => "skills/prayer_of_healing.skill.ron",
+            SkillCode::Resurrect => "skills/resurrect.skill.ron",
+            SkillCode::Stealth => "skills/stealth.skill.ron",
+            SkillCode::SinisterStrike => "skills/sinister_strike.skill.ron",
+            SkillCode::Backstab => "skills/backstab.skill.ron",
+            SkillCode::Eviscerate => "skills/eviscerate.skill.ron",
+            SkillCode::Shot => "skills/shot.skill.ron",
+            SkillCode::AimedShot => "skills/aimed_shot.skill.ron",
+            SkillCode::Consecration => "skills/consecration.skill.ron",
+            SkillCode::Blizzard => "skills/blizzard.skill.ron",
+            SkillCode::Sanctuary => "skills/sanctuary.skill.ron",
+            SkillCode::Kick => "skills/kick.skill.ron",
+            SkillCode::ArcaneMissiles => "skills/arcane_missiles.skill.ron",
+        }
+    }
+}
+
+/**
+ Handles of every [`SkillDefinition`] by [`SkillCode`].
+
+ definitions are looked up on every use, so a hot reloaded file applies from the next cast.
+*/
+#[derive(Resource, Default)]
+pub struct SkillBook {
+    handles: HashMap<SkillCode, Handle<SkillDefinition>>,
+}
+
+impl SkillBook {
+    pub fn get<'a>(
+        &self,
+        code: &SkillCode,
+        definitions: &'a Assets<SkillDefinition>,
+    ) -> Option<&'a SkillDefinition> {
+        self.handles.get(code).and_then(|h| definitions.get(h))
+    }
+}
+
+pub fn load_skill_book(mut skill_book: ResMut<SkillBook>, server: Res<AssetServer>) {
+    for code in SkillCode::ALL {
+        skill_book
+            .handles
+            .insert(code, server.load(code.asset_path()));
+    }
+}
+
+/// sent when a skill reaches its target, either instantly or by a projectile.
+#[derive(Event)]
+pub struct SkillHit {
+    pub caster: Entity,
+    pub target: Entity,
+    pub skill: SkillCode,
+    pub level: u32,
+}
+
+/// sent when a [`Charging`] caster reaches its target, landing the effects listed after the charge.
+#[derive(Event)]
+pub struct ChargeArrived {
+    pub caster: Entity,
+    pub target: Entity,
+    pub skill: SkillCode,
+    pub level: u32,
+}
+
+/// sent every time a cast or a channel tick lets its skill go, before it reaches the target.
+#[derive(Event)]
+pub struct SkillReleased {
+    pub caster: Entity,
+}
+
+/// seconds every skill is locked for after another skill starts casting.
+pub const GLOBAL_COOLDOWN: f32 = 1.0;
+
+/// seconds a cast is set back by every hit the caster takes.
+pub const CAST_PUSHBACK: f32 = 0.5;
+
+/// seconds a released skill waits for the caster's release frame before going on its own.
+pub const RELEASE_TIMEOUT: f32 = 1.0;
+
+/**
+ Skills let go by a caster whose attack clip has a [`RELEASE_EVENT`] frame.
+
+ they leave the caster on that frame instead of the moment the cast ends, see [`release_on_frame`].
+*/
+#[derive(Component, Default)]
+pub struct PendingReleases(Vec<(SkillHit, Timer)>);
+
+/// sent when a cast or channel is stopped before its end.
+#[derive(Event)]
+pub struct Interrupted {
+    pub caster: Entity,
+    pub skill: SkillCode,
+    /// the unit that interrupted the cast, `None` when the caster moved.
+    pub by: Option<Entity>,
+}
+
+/// interrupts the caster's cast on its own, when it moves or loses its target.
+pub fn cancel_cast(
+    caster: Entity,
+    skill: &mut SkillInfo,
+    interrupted_evt: &mut EventWriter<Interrupted>,
+) {
+    if let Some(skill) = skill.interrupt() {
+        interrupted_evt.send(Interrupted {
+            caster,
+            skill,
+            by: None,
+        });
+    }
 }
 
 #[derive(Component)]
 pub struct SkillInfo {
     current_skill: Option<SkillCode>,
+    skill_target: Option<Entity>,
+    queued_skill: Option<SkillCode>,
     casting_time: Stopwatch,
-    global_cooltime: Stopwatch,
+    global_cooltime: Timer,
+    cooldowns: HashMap<SkillCode, Timer>,
+    lockouts: HashMap<SpellSchool, Timer>,
     cast_time: f32,
+    /// ticks of the current channel, `0` for a regular cast.
+    channel_ticks: u32,
+    ticks_done: u32,
 }
 
 impl SkillInfo {
     pub fn new() -> Self {
         Self {
             current_skill: None,
+            skill_target: None,
+            queued_skill: None,
             casting_time: Stopwatch::default(),
-            global_cooltime: Stopwatch::default(),
+            global_cooltime: Timer::default(),
+            cooldowns: HashMap::new(),
+            lockouts: HashMap::new(),
             cast_time: 0.,
+            channel_ticks: 0,
+            ticks_done: 0,
         }
     }
 
@@ -70,8 +285,26 @@ impl SkillInfo {
         &self.current_skill
     }
 
-    pub fn set_skill(&mut self, skill: SkillCode) {
+    /// who the current skill is cast on.
+    pub fn skill_target(&self) -> Option<Entity> {
+        self.skill_target
+    }
+
+    pub fn set_skill(&mut self, skill: SkillCode, target: Option<Entity>) {
         self.current_skill = Some(skill);
+        self.skill_target = target;
+    }
+
+    /// skill to use instead of the auto attack, once the current cast is over.
+    pub fn queue_skill(&mut self, skill: SkillCode) {
+        self.queued_skill = Some(skill);
+    }
+
+    /// takes `skill` off the queue, once its cast has started.
+    fn unqueue(&mut self, skill: SkillCode) {
+        if self.queued_skill == Some(skill) {
+            self.queued_skill = None;
+        }
     }
 
     pub fn casting(&mut self, duration: f32) {
@@ -86,6 +319,51 @@ impl SkillInfo {
 
         self.casting_time.pause();
         self.cast_time = 0.;
+        self.channel_ticks = 0;
+        self.ticks_done = 0;
+    }
+
+    /// breaks the cast in progress, returning the skill it was casting.
+    pub fn interrupt(&mut self) -> Option<SkillCode> {
+        if !self.is_casting() {
+            return None;
+        }
+        self.break_casting();
+        self.current_skill
+    }
+
+    /**
+     sets a hit caster back.
+
+     a cast loses progress while a channel loses its remaining time,
+     the ticks it skips over being lost rather than landing all at once.
+    */
+    pub fn pushback(&mut self, secs: f32) {
+        if !self.is_casting() {
+            return;
+        }
+        let elapsed = self.casting_time.elapsed_secs();
+        let elapsed = if self.channel_ticks > 0 {
+            (elapsed + secs).min(self.cast_time)
+        } else {
+            (elapsed - secs).max(0.)
+        };
+        self.casting_time
+            .set_elapsed(Duration::from_secs_f32(elapsed));
+        if self.channel_ticks > 0 {
+            self.ticks_done = self.due_ticks();
+        }
+    }
+
+    pub fn lock_school(&mut self, school: SpellSchool, secs: f32) {
+        self.lockouts
+            .insert(school, Timer::from_seconds(secs, TimerMode::Once));
+    }
+
+    pub fn is_locked(&self, school: &SpellSchool) -> bool {
+        self.lockouts
+            .get(school)
+            .is_some_and(|timer| !timer.finished())
     }
 
     pub fn is_cast_done(&self) -> bool {
@@ -94,7 +372,35 @@ impl SkillInfo {
 
     pub fn tick(&mut self, delta: Duration) {
         self.casting_time.tick(delta);
+    }
+
+    pub fn tick_cooldowns(&mut self, delta: Duration) {
         self.global_cooltime.tick(delta);
+        for timer in self.cooldowns.values_mut() {
+            timer.tick(delta);
+        }
+        for timer in self.lockouts.values_mut() {
+            timer.tick(delta);
+        }
+    }
+
+    pub fn trigger_global_cooldown(&mut self, secs: f32) {
+        self.global_cooltime = Timer::from_seconds(secs, TimerMode::Once);
+    }
+
+    pub fn start_cooldown(&mut self, skill: SkillCode, secs: f32) {
+        self.cooldowns
+            .insert(skill, Timer::from_seconds(secs, TimerMode::Once));
+    }
+
+    pub fn global_cooldown_remaining(&self) -> f32 {
+        self.global_cooltime.remaining_secs()
+    }
+
+    pub fn cooldown_remaining(&self, skill: &SkillCode) -> f32 {
+        self.cooldowns
+            .get(skill)
+            .map_or(0., |timer| timer.remaining_secs())
     }
 
     pub fn is_casting(&self) -> bool {
@@ -113,6 +419,29 @@ impl SkillInfo {
         self.is_cast_done()
     }
 
+    /// advances a channel of `ticks` ticks, returning how many of them landed this frame.
+    pub fn channel(&mut self, duration: f32, ticks: u32, delta: Duration) -> u32 {
+        if !self.is_casting() {
+            self.casting(duration);
+            self.channel_ticks = ticks;
+            self.ticks_done = 0;
+        } else {
+            self.tick(delta);
+        }
+        let due = self.due_ticks();
+        let landed = due.saturating_sub(self.ticks_done);
+        self.ticks_done = due;
+        if self.is_cast_done() {
+            self.break_casting();
+        }
+        landed
+    }
+
+    /// channel ticks the elapsed time has gone through.
+    fn due_ticks(&self) -> u32 {
+        ((self.ratio().min(1.) * self.channel_ticks as f32) as u32).min(self.channel_ticks)
+    }
+
     pub fn ratio(&self) -> f32 {
         if self.cast_time == 0. {
             return 0.;
@@ -122,140 +451,668 @@ impl SkillInfo {
     // pub fn
 }
 
+pub fn tick_cooldowns(time: Res<Time>, mut entities: Query<&mut SkillInfo>) {
+    for mut skill_info in &mut entities {
+        skill_info.tick_cooldowns(time.delta());
+    }
+}
+
+/**
+ picks the skill every entity in battle casts next, and who it is cast on.
+
+ a skill queued by the player goes first, then the entity's [`Rotation`] is evaluated
+ and the class auto attack is used when no entry is usable.
+*/
 pub fn use_skill(
+    skill_book: Res<SkillBook>,
+    definitions: Res<Assets<SkillDefinition>>,
     mut entities: Query<
         (
+            Entity,
             &Transform,
-            &mut Target,
-            &Stat,
+            &Target,
             &ActionState,
-            &mut BattleState,
+            &BattleState,
             &mut SkillInfo,
+            Option<&Class>,
+            Option<&Monster>,
+            &Team,
+            Option<&Rotation>,
+            Option<&Power>,
+            Option<&ComboPoints>,
+            &Buffs,
         ),
-        Or<(With<Player>, With<Monster>)>,
+        (Or<(With<Player>, With<Monster>)>, Without<Dead>),
+    >,
+    others: Query<
+        (Entity, &Transform, &Stat, &Team, &Buffs, &Facing, Has<Dead>),
+        Or<(With<Player>, With<Monster>, With<Building>)>,
     >,
 ) {
-    for (_t, _targ, _stat, a_state, b_state, mut skill_info) in &mut entities {
-        if *a_state == ActionState::BATTLE {
-            if *b_state != BattleState::CASTING && *b_state != BattleState::RUNAWAY {
-                skill_info.set_skill(SkillCode::BaseAttack);
+    // units in the middle of a cast, an interrupt is only worth using on them.
+    let casting: Vec<Entity> = entities
+        .iter()
+        .filter(|(_, _, _, _, _, skill_info, ..)| skill_info.is_casting())
+        .map(|(ent, ..)| ent)
+        .collect();
+
+    for (
+        ent,
+        t,
+        target,
+        a_state,
+        b_state,
+        mut skill_info,
+        class,
+        monster,
+        team,
+        rotation,
+        power,
+        combo_points,
+        buffs,
+    ) in &mut entities
+    {
+        if *a_state != ActionState::BATTLE
+            || matches!(
+                *b_state,
+                BattleState::CASTING
+                    | BattleState::RUNAWAY
+                    | BattleState::EVADING
+                    | BattleState::FLEEING
+            )
+        {
+            continue;
+        }
+
+        // who a skill of the given definition would be cast on.
+        let skill_target = |definition: &SkillDefinition| -> Option<Entity> {
+            let in_range = |other_t: &Transform| {
+                t.translation.distance(other_t.translation) <= definition.range
+            };
+            match definition.targeting {
+                TargetingMode::Enemy => target.0,
+                TargetingMode::Caster => Some(ent),
+                TargetingMode::Ally => others
+                    .iter()
+                    .filter(|(_, other_t, _, other_team, _, _, dead)| {
+                        other_team.0 == team.0 && !dead && in_range(other_t)
+                    })
+                    .min_by(|a, b| a.2.hp.ratio().total_cmp(&b.2.hp.ratio()))
+                    .map(|(other, _, _, _, _, _, _)| other),
+                TargetingMode::DeadAlly => others
+                    .iter()
+                    .find(|(_, other_t, _, other_team, _, _, dead)| {
+                        other_team.0 == team.0 && *dead && in_range(other_t)
+                    })
+                    .map(|(other, _, _, _, _, _, _)| other),
+            }
+        };
+
+        // the queued skill stays queued until cast_skill starts it.
+        if let Some(queued) = skill_info.queued_skill {
+            if skill_info.cooldown_remaining(&queued) <= 0. {
+                let targ = skill_book
+                    .get(&queued, &definitions)
+                    .and_then(&skill_target);
+                if targ.is_some() {
+                    skill_info.set_skill(queued, targ);
+                    continue;
+                }
+                // nobody to cast it on, the order is dropped.
+                skill_info.queued_skill = None;
             }
         }
+
+        let enemy_distances: Vec<f32> = others
+            .iter()
+            .filter(|(_, _, _, other_team, _, _, dead)| other_team.0 != team.0 && !dead)
+            .map(|(_, other_t, _, _, _, _, _)| t.translation.distance(other_t.translation))
+            .collect();
+        let ally_health_ratios: Vec<f32> = others
+            .iter()
+            .filter(|(_, _, _, other_team, _, _, dead)| other_team.0 == team.0 && !dead)
+            .map(|(_, _, stat, _, _, _, _)| stat.hp.ratio())
+            .collect();
+
+        let chosen = rotation.and_then(|rotation| {
+            rotation.0.iter().find_map(|entry| {
+                let definition = skill_book.get(&entry.skill, &definitions)?;
+                if skill_info.cooldown_remaining(&entry.skill) > 0.
+                    || skill_info.is_locked(&definition.school)
+                    || power.is_some_and(|p| !p.can_pay(definition.cost))
+                {
+                    return None;
+                }
+                let targ = skill_target(definition)?;
+                let targ_info = others.get(targ).ok();
+                let context = RotationContext {
+                    caster_buffs: Some(buffs),
+                    target_distance: targ_info.map(|(_, targ_t, _, _, _, _, _)| {
+                        t.translation.distance(targ_t.translation)
+                    }),
+                    target_health_ratio: targ_info.map(|(_, _, stat, _, _, _, _)| stat.hp.ratio()),
+                    target_buffs: targ_info.map(|(_, _, _, _, buffs, _, _)| buffs),
+                    enemy_distances: &enemy_distances,
+                    ally_health_ratios: &ally_health_ratios,
+                    combo_points: combo_points.map_or(0, |points| points.0),
+                    behind_target: targ_info.is_some_and(|(_, targ_t, _, _, _, facing, _)| {
+                        facing.is_behind(targ_t.translation, t.translation)
+                    }),
+                    target_casting: casting.contains(&targ),
+                };
+                entry
+                    .conditions
+                    .iter()
+                    .all(|c| c.check(&context))
+                    .then_some((entry.skill, Some(targ)))
+            })
+        });
+
+        let (skill, targ) = chosen.unwrap_or_else(|| {
+            let auto_attack = match (class, monster, &team.0) {
+                (Some(class), _, TeamType::PLAYER) => class.auto_attack(),
+                (_, Some(monster), _) => monster.auto_attack,
+                _ => SkillCode::MagicBolt,
+            };
+            (auto_attack, target.0)
+        });
+        skill_info.set_skill(skill, targ);
     }
 }
 
-pub fn base_attack(
+/**
+ casts the current skill of every entity in battle on the target picked by [`use_skill`].
+
+ when an enemy is out of the skill's range, the caster is put in [`BattleState::MOVE`] to close in,
+ and in [`BattleState::RUNAWAY`] to back off when it stands closer than the skill's minimum range.
+*/
+pub fn cast_skill(
     mut command: Commands,
     time: Res<Time>,
-    mut attacked_evt: EventWriter<Attacked>,
-    mut entities: Query<
+    skill_book: Res<SkillBook>,
+    definitions: Res<Assets<SkillDefinition>>,
+    mut hit_evt: EventWriter<SkillHit>,
+    mut released_evt: EventWriter<SkillReleased>,
+    mut casters: Query<
         (
             Entity,
             &Transform,
-            &Target,
-            &mut Stat,
+            &Stat,
             &ActionState,
             &mut BattleState,
             &mut SkillInfo,
-            Option<&Class>,
+            Option<&mut Power>,
+            &Buffs,
             &Team,
+            Option<&mut PendingReleases>,
         ),
-        Or<(With<Player>, With<Monster>)>,
+        (Or<(With<Player>, With<Monster>)>, Without<Dead>),
     >,
+    targets: Query<&Transform, Or<(With<Player>, With<Monster>, With<Building>)>>,
+    mut interrupted_evt: EventWriter<Interrupted>,
 ) {
-    let mut combinations = entities.iter_combinations_mut();
-    while let Some([a, b]) = combinations.fetch_next() {
-        if a.0 == b.0 {
+    for (ent, t, stat, a_state, mut b_state, mut skill, mut power, buffs, team, mut pending) in
+        &mut casters
+    {
+        if *a_state != ActionState::BATTLE {
             continue;
         }
-
-        let (ent, t, target, stat, _a_state, mut b_state, mut skill, class, team) = a;
-        let cur_skill = skill.current_skill();
-        let code = if cur_skill.is_some() {
-            cur_skill.clone().unwrap()
-        } else {
+        // fleeing units keep running until the buff runs out.
+        if buffs.has(BuffKind::Fleeing) {
+            cancel_cast(ent, &mut skill, &mut interrupted_evt);
+            *b_state = BattleState::FLEEING;
+            continue;
+        } else if *b_state == BattleState::FLEEING {
+            *b_state = BattleState::IDLE;
+        }
+        let Some(code) = *skill.current_skill() else {
             continue;
         };
-
-        let Some(targ_ent) = target.0 else {
+        let Some(definition) = skill_book.get(&code, &definitions) else {
+            continue;
+        };
+        let Some(targ_ent) = skill.skill_target() else {
+            continue;
+        };
+        let Ok(targ_t) = targets.get(targ_ent) else {
+            cancel_cast(ent, &mut skill, &mut interrupted_evt);
+            *b_state = BattleState::IDLE;
             continue;
         };
 
-        //only target
-        if targ_ent != b.0 {
+        let starting = !skill.is_casting();
+        if starting {
+            let distance = t.translation.distance(targ_t.translation);
+            if distance < definition.min_range {
+                *b_state = BattleState::RUNAWAY;
+                continue;
+            }
+            if distance > definition.range {
+                *b_state = if definition.targeting == TargetingMode::Enemy {
+                    BattleState::MOVE
+                } else {
+                    BattleState::IDLE
+                };
+                continue;
+            }
+            if skill.cooldown_remaining(&code) > 0.
+                || skill.is_locked(&definition.school)
+                || (!definition.ignore_global_cooldown && skill.global_cooldown_remaining() > 0.)
+            {
+                *b_state = BattleState::IDLE;
+                continue;
+            }
+            if power.as_ref().is_some_and(|p| !p.can_pay(definition.cost)) {
+                *b_state = BattleState::IDLE;
+                continue;
+            }
+        }
+
+        let haste = stat.haste_scale(buffs.haste());
+        if starting {
+            skill.unqueue(code);
+        }
+        if starting && !definition.ignore_global_cooldown {
+            skill.trigger_global_cooldown(GLOBAL_COOLDOWN * haste);
+        }
+
+        // a channel is paid upfront and lands on every tick, a cast is paid and lands at its end.
+        let landed = if definition.channel_ticks > 0 {
+            if starting {
+                if let Some(power) = power.as_mut() {
+                    if !power.spend(definition.cost) {
+                        continue;
+                    }
+                }
+                if definition.cooldown > 0. {
+                    skill.start_cooldown(code, definition.cooldown * haste);
+                }
+            }
+            skill.channel(
+                definition.cast_time * haste,
+                definition.channel_ticks,
+                time.delta(),
+            )
+        } else {
+            let done = skill.cast(definition.cast_time * haste, time.delta());
+            if done {
+                if let Some(power) = power.as_mut() {
+                    if !power.spend(definition.cost) {
+                        continue;
+                    }
+                }
+                if definition.cooldown > 0. {
+                    skill.start_cooldown(code, definition.cooldown * haste);
+                }
+            }
+            done as u32
+        };
+        *b_state = if skill.is_casting() {
+            BattleState::CASTING
+        } else {
+            BattleState::IDLE
+        };
+
+        for _ in 0..landed {
+            released_evt.send(SkillReleased { caster: ent });
+            let hit = SkillHit {
+                caster: ent,
+                target: targ_ent,
+                skill: code,
+                level: stat.level,
+            };
+            match pending.as_mut() {
+                Some(pending) => pending
+                    .0
+                    .push((hit, Timer::from_seconds(RELEASE_TIMEOUT, TimerMode::Once))),
+                None => release_skill(
+                    &mut command,
+                    &mut hit_evt,
+                    definition,
+                    hit,
+                    team.0,
+                    t,
+                    targ_t,
+                ),
+            }
+        }
+    }
+}
+
+/**
+ lets the [`PendingReleases`] of every caster go on its release frame.
+
+ a release whose frame never comes, the clip cut short or missing, goes after [`RELEASE_TIMEOUT`].
+ the ones of a caster that died are dropped.
+*/
+pub fn release_on_frame(
+    mut command: Commands,
+    time: Res<Time>,
+    skill_book: Res<SkillBook>,
+    definitions: Res<Assets<SkillDefinition>>,
+    mut frame_evt: EventReader<AnimationFrameEvent>,
+    mut hit_evt: EventWriter<SkillHit>,
+    mut casters: Query<(Entity, &Transform, &Team, &mut PendingReleases, Has<Dead>)>,
+    targets: Query<&Transform, Or<(With<Player>, With<Monster>, With<Building>)>>,
+) {
+    let released: HashSet<Entity> = frame_evt
+        .read()
+        .filter(|event| event.name == RELEASE_EVENT)
+        .map(|event| event.entity)
+        .collect();
+    for (ent, t, team, mut pending, dead) in &mut casters {
+        if dead {
+            pending.0.clear();
             continue;
         }
+        // a frame lets the oldest release go, the next ones wait for their own attack clip.
+        let mut on_frame = released.contains(&ent);
+        for (hit, mut timer) in std::mem::take(&mut pending.0) {
+            timer.tick(time.delta());
+            if on_frame {
+                on_frame = false;
+            } else if !timer.finished() {
+                pending.0.push((hit, timer));
+                continue;
+            }
+            let Some(definition) = skill_book.get(&hit.skill, &definitions) else {
+                continue;
+            };
+            let Ok(targ_t) = targets.get(hit.target) else {
+                continue;
+            };
+            release_skill(
+                &mut command,
+                &mut hit_evt,
+                definition,
+                hit,
+                team.0,
+                t,
+                targ_t,
+            );
+        }
+    }
+}
 
-        let mut targ_stat = b.3;
+/// sends the skill of `hit` from `from` to its target at `to`, by a projectile when it has one.
+pub fn release_skill(
+    command: &mut Commands,
+    hit_evt: &mut EventWriter<SkillHit>,
+    definition: &SkillDefinition,
+    hit: SkillHit,
+    team: TeamType,
+    from: &Transform,
+    to: &Transform,
+) {
+    let Some(projectile) = &definition.projectile else {
+        hit_evt.send(hit);
+        return;
+    };
+    let direction = (to.translation - from.translation).truncate();
+    let target = match projectile.projectile_type {
+        ProjectileType::Targeting => Target(Some(hit.target)),
+        ProjectileType::NonTargeting => Target(None),
+    };
+    spawn_projectile(
+        command,
+        Projectile::new(
+            projectile.speed,
+            hit.caster,
+            team,
+            hit.skill,
+            hit.level,
+            projectile.lifetime,
+            projectile.projectile_type,
+        )
+        .with_direction(direction)
+        .with_pierce(projectile.pierce),
+        Vec2::splat(projectile.size),
+        projectile.color(),
+        *from,
+        target,
+    );
+}
 
-        if code == SkillCode::BaseAttack {
-            if team.0 == TeamType::PLAYER {
-                let Some(class) = class else {
+/**
+ turns every [`SkillEffect`] of a landed skill into battle events.
+
+ area skills apply their effects to every unit of the area's relation around the target.
+*/
+pub fn apply_skill_hit(
+    mut commands: Commands,
+    skill_book: Res<SkillBook>,
+    definitions: Res<Assets<SkillDefinition>>,
+    mut hit_evt: EventReader<SkillHit>,
+    mut arrived_evt: EventReader<ChargeArrived>,
+    mut attacked_evt: EventWriter<Attacked>,
+    mut damage_evt: EventWriter<Damage>,
+    mut heal_evt: EventWriter<Heal>,
+    mut ground_effect_evt: EventWriter<SpawnGroundEffect>,
+    mut units: Query<
+        (
+            Entity,
+            &Transform,
+            &Team,
+            &Stat,
+            &mut Buffs,
+            &mut Target,
+            &Facing,
+            Has<Dead>,
+        ),
+        Or<(With<Player>, With<Monster>, With<Building>)>,
+    >,
+    mut combo_points: Query<&mut ComboPoints>,
+    mut skill_infos: Query<&mut SkillInfo>,
+    mut interrupted_evt: EventWriter<Interrupted>,
+) {
+    let arrivals: Vec<SkillHit> = arrived_evt
+        .read()
+        .map(|arrived| SkillHit {
+            caster: arrived.caster,
+            target: arrived.target,
+            skill: arrived.skill,
+            level: arrived.level,
+        })
+        .collect();
+    let hits = hit_evt
+        .read()
+        .map(|hit| (hit, false))
+        .chain(arrivals.iter().map(|hit| (hit, true)));
+    for (hit, arrived) in hits {
+        let Some(definition) = skill_book.get(&hit.skill, &definitions) else {
+            continue;
+        };
+        // the effects after a charge wait for the caster to get there.
+        let effects = match definition
+            .effects
+            .iter()
+            .position(|effect| matches!(effect, SkillEffect::Charge { .. }))
+        {
+            Some(charge) if arrived => &definition.effects[charge + 1..],
+            Some(charge) => &definition.effects[..=charge],
+            None if arrived => continue,
+            None => &definition.effects[..],
+        };
+        let targets: Vec<Entity> = match &definition.area {
+            Some(area) => {
+                let (Ok(center), Ok(caster)) = (units.get(hit.target), units.get(hit.caster))
+                else {
                     continue;
                 };
-                match class {
-                    Class::NONE => {
-                        if skill.cast(0.2, time.delta()) {
-                            targ_stat.hp.current -= (stat.level * 5) as f32;
-                            attacked_evt.send(Attacked::new(ent, targ_ent));
-                            //info!("base attack!");
+                let (center, caster_team) = (center.1.translation, caster.2 .0);
+                units
+                    .iter()
+                    .filter(|(_, t, team, _, _, _, _, dead)| {
+                        !dead
+                            && area.affects.matches(&caster_team, &team.0)
+                            && t.translation.distance(center) <= area.radius
+                    })
+                    .map(|(ent, _, _, _, _, _, _, _)| ent)
+                    .collect()
+            }
+            None => vec![hit.target],
+        };
+
+        let caster = units
+            .get(hit.caster)
+            .ok()
+            .map(|caster| (caster.1.translation, caster.2 .0));
+        let caster_position = caster.map(|(position, _)| position);
+
+        for target in targets {
+            let Ok((_, t, _, stat, mut buffs, mut targ, facing, dead)) = units.get_mut(target)
+            else {
+                continue;
+            };
+            let behind =
+                caster_position.is_some_and(|position| facing.is_behind(t.translation, position));
+            for effect in effects {
+                match effect {
+                    SkillEffect::Damage {
+                        amount,
+                        per_level,
+                        damage_type,
+                        behind_bonus,
+                    } => {
+                        let bonus = if behind { 1. + behind_bonus } else { 1. };
+                        damage_evt.send(Damage {
+                            attacker: hit.caster,
+                            damage: scaled(*amount, *per_level, hit.level) * bonus,
+                            damage_type: damage_type.clone(),
+                            attacked: target,
+                        });
+                        attacked_evt.send(Attacked::new(hit.caster, target));
+                    }
+                    SkillEffect::Heal { amount, per_level } => heal_evt.send(Heal {
+                        healer: hit.caster,
+                        value: scaled(*amount, *per_level, hit.level),
+                        heal_type: HealType::direct,
+                        healed: target,
+                    }),
+                    SkillEffect::HealOverTime {
+                        buff,
+                        amount,
+                        per_level,
+                        duration,
+                        interval,
+                    } => buffs.add_periodic(
+                        *buff,
+                        hit.caster,
+                        *duration,
+                        *interval,
+                        scaled(*amount, *per_level, hit.level),
+                    ),
+                    SkillEffect::ApplyBuff { buff, duration } => {
+                        buffs.add(*buff, hit.caster, *duration);
+                    }
+                    SkillEffect::Taunt { duration } => {
+                        buffs.add(BuffKind::Taunted, hit.caster, *duration);
+                        *targ = Target(Some(hit.caster));
+                    }
+                    SkillEffect::Charge { speed } => {
+                        if let Some(mut caster) = commands.get_entity(hit.caster) {
+                            caster.insert(Charging {
+                                target,
+                                speed: *speed,
+                                skill: hit.skill,
+                                level: hit.level,
+                            });
                         }
                     }
-                    Class::KNIGHT => todo!(),
-                    Class::MAGE => {
-                        *b_state = if skill.is_casting() {
-                            BattleState::CASTING
-                        } else {
-                            BattleState::IDLE
+                    SkillEffect::ComboPoints(points) => {
+                        if let Ok(mut combo) = combo_points.get_mut(hit.caster) {
+                            combo.add(*points);
+                        }
+                    }
+                    SkillEffect::Finisher {
+                        amount_per_point,
+                        per_level,
+                        damage_type,
+                    } => {
+                        let Ok(mut combo) = combo_points.get_mut(hit.caster) else {
+                            continue;
                         };
-                        if skill.cast(1.0, time.delta()) {
-                            spawn_projectile(
-                                &mut command,
-                                Projectile::new(
-                                    1000.,
-                                    10.,
-                                    ent,
-                                    Some(DamageType::Magic),
-                                    None,
-                                    2.,
-                                    ProjectileType::Targeting,
-                                ),
-                                t.clone(),
-                                Target(Some(targ_ent)),
-                            );
+                        let points = combo.consume();
+                        if points == 0 {
+                            continue;
                         }
+                        damage_evt.send(Damage {
+                            attacker: hit.caster,
+                            damage: scaled(*amount_per_point, *per_level, hit.level)
+                                * points as f32,
+                            damage_type: damage_type.clone(),
+                            attacked: target,
+                        });
+                        attacked_evt.send(Attacked::new(hit.caster, target));
                     }
-                    Class::PRIEST => todo!(),
-                    Class::ROGUE => todo!(),
-                    Class::HUNTER => todo!(),
-                }
-            } else if team.0 == TeamType::MONSTER {
-                if code == SkillCode::BaseAttack {
-                    *b_state = if skill.is_casting() {
-                        BattleState::CASTING
-                    } else {
-                        BattleState::IDLE
-                    };
-                    if skill.cast(1.0, time.delta()) {
-                        spawn_projectile(
-                            &mut command,
-                            Projectile::new(
-                                1000.,
-                                10.,
-                                ent,
-                                Some(DamageType::Magic),
-                                None,
-                                2.,
-                                ProjectileType::Targeting,
-                            ),
-                            t.clone(),
-                            Target(Some(targ_ent)),
-                        );
+                    SkillEffect::GroundEffect(ground_effect) => {
+                        if let Some((_, team)) = caster {
+                            ground_effect_evt.send(SpawnGroundEffect {
+                                definition: ground_effect.clone(),
+                                owner: hit.caster,
+                                team,
+                                level: hit.level,
+                                position: t.translation,
+                            });
+                        }
+                    }
+                    SkillEffect::Interrupt { lockout } => {
+                        let Ok(mut skill) = skill_infos.get_mut(target) else {
+                            continue;
+                        };
+                        // only a cast in progress gets its school locked.
+                        let Some(interrupted) = skill.interrupt() else {
+                            continue;
+                        };
+                        if let Some(casting) = skill_book.get(&interrupted, &definitions) {
+                            skill.lock_school(casting.school, *lockout);
+                        }
+                        interrupted_evt.send(Interrupted {
+                            caster: target,
+                            skill: interrupted,
+                            by: Some(hit.caster),
+                        });
+                    }
+                    SkillEffect::Resurrect { health_ratio } => {
+                        if dead {
+                            heal_evt.send(Heal {
+                                healer: hit.caster,
+                                value: stat.hp.max * health_ratio,
+                                heal_type: HealType::resurrect,
+                                healed: target,
+                            });
+                        }
                     }
                 }
-            } else {
-                continue;
             }
         }
     }
 }
+
+/// logs the casts broken by another unit, the ones given up by their caster left out.
+pub fn announce_interrupts(
+    skill_book: Res<SkillBook>,
+    definitions: Res<Assets<SkillDefinition>>,
+    mut interrupted_evt: EventReader<Interrupted>,
+) {
+    for interrupted in interrupted_evt.read() {
+        let Some(by) = interrupted.by else {
+            continue;
+        };
+        if let Some(definition) = skill_book.get(&interrupted.skill, &definitions) {
+            info!(
+                "{} of {:?} interrupted by {:?}",
+                definition.name, interrupted.caster, by
+            );
+        }
+    }
+}
+
+/// every damage taken sets the attacked unit's cast back by [`CAST_PUSHBACK`].
+pub fn pushback_casts(mut damage_evt: EventReader<Damage>, mut casters: Query<&mut SkillInfo>) {
+    for damage in damage_evt.read() {
+        if let Ok(mut skill) = casters.get_mut(damage.attacked) {
+            skill.pushback(CAST_PUSHBACK);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use bevy::{asset::Asset, reflect::TypePath};
+use bevy_render::color::Color;
+use serde::Deserialize;
+
+use super::{
+    battle::DamageType, buff::BuffKind, components::TeamType,
+    ground_effect::GroundEffectDefinition, projectile::ProjectileType,
+};
+
+/**
+ Data driven description of a skill, loaded from the `skills` asset folder (`.skill.ron`).
+
+ a skill is cast on a target picked by [`TargetingMode`], optionally travels as a projectile,
+ and applies every [`SkillEffect`] to the target when it hits.
+ a projectile skill with an `area` explodes on impact.
+ a channelled skill lands `channel_ticks` times over its cast time instead of once at the end.
+*/
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct SkillDefinition {
+    pub name: String,
+    /// interrupts lock out the whole school of the interrupted skill.
+    #[serde(default)]
+    pub school: SpellSchool,
+    pub cast_time: f32,
+    #[serde(default)]
+    pub channel_ticks: u32,
+    #[serde(default)]
+    pub cooldown: f32,
+    /// auto attacks keep swinging through the global cooldown.
+    #[serde(default)]
+    pub ignore_global_cooldown: bool,
+    pub range: f32,
+    /// the caster backs off from targets closer than this.
+    #[serde(default)]
+    pub min_range: f32,
+    #[serde(default)]
+    pub cost: f32,
+    pub targeting: TargetingMode,
+    #[serde(default)]
+    pub projectile: Option<ProjectileDefinition>,
+    /// when set, the effects land on every unit of the relation around the target.
+    #[serde(default)]
+    pub area: Option<AreaDefinition>,
+    pub effects: Vec<SkillEffect>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SpellSchool {
+    #[default]
+    Physical,
+    Arcane,
+    Fire,
+    Frost,
+    Holy,
+    Nature,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TargetingMode {
+    Enemy,
+    /// the living ally with the lowest health ratio in range.
+    Ally,
+    /// a dead ally in range.
+    DeadAlly,
+    Caster,
+}
+
+/// relation of a unit to the caster.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Relation {
+    Hostile,
+    Allied,
+}
+
+impl Relation {
+    pub fn matches(&self, caster: &TeamType, other: &TeamType) -> bool {
+        match self {
+            Relation::Hostile => caster != other,
+            Relation::Allied => caster == other,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AreaDefinition {
+    pub radius: f32,
+    pub affects: Relation,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProjectileDefinition {
+    /// skillshots are `NonTargeting`, fired toward where the target stands.
+    #[serde(default)]
+    pub projectile_type: ProjectileType,
+    /// extra units a skillshot goes through.
+    #[serde(default)]
+    pub pierce: u32,
+    pub speed: f32,
+    pub lifetime: f32,
+    pub size: f32,
+    pub color: (f32, f32, f32),
+}
+
+impl ProjectileDefinition {
+    pub fn color(&self) -> Color {
+        Color::rgb(self.color.0, self.color.1, self.color.2)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum SkillEffect {
+    Damage {
+        amount: f32,
+        #[serde(default)]
+        per_level: f32,
+        damage_type: DamageType,
+        /// extra damage ratio when the caster stands behind the target, `0.5` for +50%.
+        #[serde(default)]
+        behind_bonus: f32,
+    },
+    Heal {
+        amount: f32,
+        #[serde(default)]
+        per_level: f32,
+    },
+    /// heals the target every `interval` seconds while the buff lasts.
+    HealOverTime {
+        buff: BuffKind,
+        amount: f32,
+        #[serde(default)]
+        per_level: f32,
+        duration: f32,
+        interval: f32,
+    },
+    ApplyBuff {
+        buff: BuffKind,
+        duration: f32,
+    },
+    /// makes the target attack the caster for the duration.
+    Taunt {
+        duration: f32,
+    },
+    /// rushes the caster to the target, the effects listed after it land once it gets there.
+    Charge {
+        speed: f32,
+    },
+    /// adds combo points to the caster.
+    ComboPoints(u32),
+    /// spends every combo point of the caster, dealing damage for each of them.
+    Finisher {
+        amount_per_point: f32,
+        #[serde(default)]
+        per_level: f32,
+        damage_type: DamageType,
+    },
+    /// stops the target's cast and locks its school for `lockout` seconds.
+    Interrupt {
+        lockout: f32,
+    },
+    /// leaves a zone on the ground where the target stands.
+    GroundEffect(GroundEffectDefinition),
+    /// brings a dead target back with a ratio of its health.
+    Resurrect {
+        health_ratio: f32,
+    },
+}
+
+/// effect value scaled by the caster's level.
+pub fn scaled(amount: f32, per_level: f32, level: u32) -> f32 {
+    amount + per_level * level as f32
+}
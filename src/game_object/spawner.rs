@@ -0,0 +1,213 @@
+use bevy::{
+    app::{App, Plugin, Update},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{Event, EventReader},
+        query::{With, Without},
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res},
+    },
+    time::{Time, Timer, TimerMode},
+    transform::{components::Transform, TransformBundle},
+};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::AppState;
+
+use super::{
+    chunk::Dormant,
+    ground_effect::ZoneShape,
+    monster::{spawn_monster_by_id, Monster, MonsterTemplates},
+};
+
+pub struct SpawnerPlugin;
+
+impl Plugin for SpawnerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ToggleSpawner>().add_systems(
+            Update,
+            (toggle_spawners, run_spawners)
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// a monster template a spawner can place, picked proportionally to its `weight`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SpawnEntry {
+    pub monster: String,
+    pub level: u32,
+    pub weight: u32,
+}
+
+/// spawns `size` monsters every `interval` seconds, `waves` times or forever.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct WaveSchedule {
+    pub interval: f32,
+    pub size: u32,
+    #[serde(default)]
+    pub waves: Option<u32>,
+}
+
+/**
+ Description of an area populated with monsters.
+
+ monsters appear at random points of the `region` around the spawner, never more than `cap` alive.
+ without `waves` a dead monster is replaced after `respawn_delay` seconds.
+*/
+#[derive(Deserialize, Debug, Clone)]
+pub struct SpawnerDefinition {
+    /// what [`ToggleSpawner`] events refer to.
+    pub name: String,
+    pub region: ZoneShape,
+    pub monsters: Vec<SpawnEntry>,
+    pub cap: usize,
+    pub respawn_delay: f32,
+    #[serde(default)]
+    pub waves: Option<WaveSchedule>,
+    #[serde(default = "active_by_default")]
+    pub active: bool,
+}
+
+fn active_by_default() -> bool {
+    true
+}
+
+#[derive(Component)]
+pub struct Spawner {
+    definition: SpawnerDefinition,
+    active: bool,
+    alive: Vec<Entity>,
+    timer: Timer,
+    waves_done: u32,
+}
+
+impl Spawner {
+    pub fn new(definition: SpawnerDefinition) -> Self {
+        let delay = match definition.waves {
+            Some(waves) => waves.interval,
+            None => definition.respawn_delay,
+        };
+        Self {
+            active: definition.active,
+            definition,
+            alive: Vec::new(),
+            timer: Timer::from_seconds(delay, TimerMode::Repeating),
+            waves_done: 0,
+        }
+    }
+
+    fn pick_entry(&self, rng: &mut impl Rng) -> Option<&SpawnEntry> {
+        let total: u32 = self.definition.monsters.iter().map(|e| e.weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = rng.gen_range(0..total);
+        self.definition.monsters.iter().find(|entry| {
+            if roll < entry.weight {
+                return true;
+            }
+            roll -= entry.weight;
+            false
+        })
+    }
+}
+
+/// turns every spawner with the given name on or off, for encounters and quests.
+#[derive(Event)]
+pub struct ToggleSpawner {
+    pub name: String,
+    pub active: bool,
+}
+
+pub fn spawn_spawner(commands: &mut Commands, definition: SpawnerDefinition, transform: Transform) {
+    commands.spawn((
+        Spawner::new(definition),
+        TransformBundle::from_transform(transform),
+    ));
+}
+
+pub fn toggle_spawners(
+    mut toggle_evt: EventReader<ToggleSpawner>,
+    mut spawners: Query<&mut Spawner>,
+) {
+    for evt in toggle_evt.read() {
+        for mut spawner in &mut spawners {
+            if spawner.definition.name == evt.name {
+                spawner.active = evt.active;
+                spawner.timer.reset();
+            }
+        }
+    }
+}
+
+/// keeps the population of every active spawner up, or sends its waves when they're due.
+///
+/// dormant spawners wait for their chunk to be loaded again.
+pub fn run_spawners(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut spawners: Query<(&Transform, &mut Spawner), Without<Dormant>>,
+    monsters: Query<(), With<Monster>>,
+    mut templates: MonsterTemplates,
+) {
+    let mut rng = rand::thread_rng();
+    for (t, mut spawner) in &mut spawners {
+        spawner.alive.retain(|ent| monsters.contains(*ent));
+        if !spawner.active {
+            continue;
+        }
+        // the timer waits for the registry, the first wave isn't lost while it loads.
+        let loaded = spawner
+            .definition
+            .monsters
+            .iter()
+            .any(|entry| templates.get(&entry.monster).is_some());
+        if !loaded {
+            continue;
+        }
+        spawner.timer.tick(time.delta());
+        if !spawner.timer.just_finished() {
+            continue;
+        }
+
+        let room = spawner.definition.cap.saturating_sub(spawner.alive.len());
+        let count = match spawner.definition.waves {
+            Some(waves) => {
+                if waves.waves.is_some_and(|limit| spawner.waves_done >= limit) {
+                    continue;
+                }
+                room.min(waves.size as usize)
+            }
+            None => room.min(1),
+        };
+
+        let mut spawned = 0;
+        for _ in 0..count {
+            let Some(entry) = spawner.pick_entry(&mut rng).cloned() else {
+                break;
+            };
+            let position =
+                t.translation.truncate() + spawner.definition.region.random_point(&mut rng);
+            // an entry without a template is skipped, the others still spawn.
+            let Some(id) = spawn_monster_by_id(
+                &mut commands,
+                &mut templates,
+                &entry.monster,
+                entry.level,
+                Transform::from_translation(position.extend(t.translation.z)),
+            ) else {
+                continue;
+            };
+            spawner.alive.push(id);
+            spawned += 1;
+        }
+        // a wave held back by the cap doesn't count towards the limit.
+        if spawned > 0 && spawner.definition.waves.is_some() {
+            spawner.waves_done += 1;
+        }
+    }
+}
@@ -1,39 +1,40 @@
-use std::{cell::RefCell, rc::Rc, time::Duration};
+use std::{cell::RefCell, rc::Rc};
 
+use crate::{
+    resources::resource::SelectedList,
+    states::{ActionState, BattleState},
+};
 use bevy::{
+    asset::AssetServer,
     ecs::{
-        bundle::Bundle,
-        component::Component,
         entity::Entity,
         event::EventReader,
         query::{Changed, Or, With, Without},
         system::{Commands, Query, Res},
     },
+    hierarchy::BuildChildren,
     log::info,
     math::{Vec2, Vec3},
     prelude::default,
     render::color::Color,
     sprite::{Anchor, Sprite, SpriteBundle},
+    text::{Text, Text2dBundle, TextStyle},
     time::{Time, Timer, TimerMode},
     transform::components::Transform,
 };
-use rand::Rng;
-
-use crate::{
-    resources::resource::SelectedList,
-    states::{ActionState, BattleState},
-};
 
 use super::{
-    battle::Stat,
+    battle::{Power, PowerType, Stat},
+    building::Building,
     components::{
         CastingBar, CastingBarBundle, CastingBarType, HealthBar, HealthBarBundle, HealthBarType,
-        Team,
+        PowerBar, PowerBarBundle, PowerBarType, Team,
     },
-    monster::{spawn_monster, Monster},
+    monster::Monster,
     player::Player,
     projectile::Projectile,
-    skill::SkillInfo,
+    render_layer::RenderLayer,
+    skill::{Interrupted, SkillInfo},
 };
 
 pub fn spawn_healthbar(command: &mut Commands, id: Entity, transform: Transform) {
@@ -108,7 +109,7 @@ pub fn draw_healthbar(
     entities: Query<
         (&Transform, &Stat, &Sprite),
         (
-            Or<(With<Monster>, With<Player>)>,
+            Or<(With<Monster>, With<Player>, With<Building>)>,
             Without<HealthBar>,
             Without<Projectile>,
         ),
@@ -147,13 +148,14 @@ pub fn draw_healthbar(
                 bar_t.translation = Vec3::new(
                     ent_t.translation.x - bar.width / 2.,
                     ent_t.translation.y + margin,
-                    if bar.bar_type == HealthBarType::FRONT {
-                        3.
-                    } else if bar.bar_type == HealthBarType::ANIMATION {
-                        2.
-                    } else {
-                        1.
-                    },
+                    RenderLayer::Overhead.z()
+                        + if bar.bar_type == HealthBarType::FRONT {
+                            0.3
+                        } else if bar.bar_type == HealthBarType::ANIMATION {
+                            0.2
+                        } else {
+                            0.1
+                        },
                 );
                 // info!("size : {:?}", size);
             }
@@ -161,6 +163,103 @@ pub fn draw_healthbar(
     }
 }
 
+pub fn spawn_powerbar(
+    command: &mut Commands,
+    id: Entity,
+    power_type: PowerType,
+    transform: Transform,
+) {
+    let color = match power_type {
+        PowerType::Mana => Color::rgb(0.2, 0.4, 1.),
+        PowerType::Rage => Color::rgb(0.9, 0.1, 0.1),
+        PowerType::Energy => Color::rgb(1., 0.9, 0.2),
+    };
+    command.spawn(PowerBarBundle {
+        sprite: SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(Vec2::new(70., 6.)),
+                anchor: Anchor::CenterLeft,
+                ..default()
+            },
+            transform,
+            ..default()
+        },
+        power_bar: PowerBar {
+            bar_type: PowerBarType::FRONT,
+            target: Some(id),
+            width: 70.,
+        },
+    });
+    //BACKGROUND power_bar
+    command.spawn(PowerBarBundle {
+        sprite: SpriteBundle {
+            sprite: Sprite {
+                color: Color::BLACK,
+                custom_size: Some(Vec2::new(74., 8.)),
+                anchor: Anchor::CenterLeft,
+                ..default()
+            },
+            transform,
+            ..default()
+        },
+        power_bar: PowerBar {
+            bar_type: PowerBarType::BACKGROUND,
+            target: Some(id),
+            width: 74.,
+        },
+    });
+}
+
+/// draws the [`Power`] pool right under the health bar.
+pub fn draw_powerbar(
+    entities: Query<
+        (&Transform, &Power, &Sprite),
+        (
+            Or<(With<Monster>, With<Player>)>,
+            Without<PowerBar>,
+            Without<Projectile>,
+        ),
+    >,
+    mut bars: Query<(&mut Transform, &PowerBar, &mut Sprite), With<PowerBar>>,
+) {
+    let top_margin = 19.;
+
+    for (mut bar_t, bar, mut sprite) in &mut bars {
+        let Some(mut size) = sprite.custom_size else {
+            continue;
+        };
+        let Some(ent) = bar.target else {
+            continue;
+        };
+        let Ok((ent_t, power, ent_sprite)) = entities.get(ent) else {
+            continue;
+        };
+
+        let margin = if let Some(size) = ent_sprite.custom_size {
+            size.y / 2. + top_margin
+        } else {
+            top_margin
+        };
+
+        if bar.bar_type == PowerBarType::FRONT {
+            size.x = power.ratio() * bar.width;
+        }
+
+        sprite.custom_size = Some(size);
+        bar_t.translation = Vec3::new(
+            ent_t.translation.x - bar.width / 2.,
+            ent_t.translation.y + margin,
+            RenderLayer::Overhead.z()
+                + if bar.bar_type == PowerBarType::FRONT {
+                    0.2
+                } else {
+                    0.1
+                },
+        );
+    }
+}
+
 pub fn spawn_castingbar(command: &mut Commands, id: Entity, transform: Transform) {
     let width = 74.;
     let height = 10.;
@@ -184,12 +283,21 @@ pub fn spawn_castingbar(command: &mut Commands, id: Entity, transform: Transform
             current: 1.,
             max: 1.,
             visibility: false,
+            interrupted: Timer::default(),
+            label: None,
         },
     });
 }
 
+/// how long a broken cast keeps its bar up, reading "Interrupted".
+const INTERRUPTED_DISPLAY_SECS: f32 = 1.;
+
 pub fn update_castingbar(
-    mut bars: Query<(&mut Transform, &CastingBar, &mut Sprite), With<CastingBar>>,
+    mut command: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut interrupted_evt: EventReader<Interrupted>,
+    mut bars: Query<(Entity, &mut Transform, &mut CastingBar, &mut Sprite), With<CastingBar>>,
     entities: Query<
         (&Transform, &Stat, &Sprite, &BattleState, &SkillInfo),
         (
@@ -199,8 +307,10 @@ pub fn update_castingbar(
         ),
     >,
 ) {
-    for (mut t, bar, mut sprite) in &mut bars {
-        let top_margin = 15.;
+    let interrupted: Vec<Entity> = interrupted_evt.read().map(|evt| evt.caster).collect();
+
+    for (bar_ent, mut t, mut bar, mut sprite) in &mut bars {
+        let top_margin = 8.;
         let mut size = if sprite.custom_size.is_some() {
             sprite.custom_size.unwrap()
         } else {
@@ -220,14 +330,48 @@ pub fn update_castingbar(
             //info!("continue here.");
             continue;
         };
-        if *b_state == BattleState::CASTING {
+
+        if interrupted.contains(&ent) {
+            bar.interrupted = Timer::from_seconds(INTERRUPTED_DISPLAY_SECS, TimerMode::Once);
+            if bar.label.is_none() {
+                let label = command
+                    .spawn(Text2dBundle {
+                        text: Text::from_section(
+                            "Interrupted",
+                            TextStyle {
+                                font: asset_server.load("Consolas.ttf"),
+                                font_size: 14.,
+                                color: Color::WHITE,
+                            },
+                        ),
+                        transform: Transform::from_xyz(bar.width / 2., 0., 1.),
+                        ..Default::default()
+                    })
+                    .id();
+                command.entity(bar_ent).add_child(label);
+                bar.label = Some(label);
+            }
+        }
+        bar.interrupted.tick(time.delta());
+        let showing_interrupt = !bar.interrupted.finished();
+        if !showing_interrupt {
+            if let Some(label) = bar.label.take() {
+                command.entity(label).despawn();
+            }
+        }
+
+        if showing_interrupt || *b_state == BattleState::CASTING {
             let margin = if let Some(size) = ent_sprite.custom_size {
                 size.y / 2. + top_margin
             } else {
                 top_margin
             };
 
-            let target_width = skill.ratio() * bar.width;
+            let target_width = if showing_interrupt {
+                bar.width
+            } else {
+                skill.ratio() * bar.width
+            };
             //info!("targ :{:}", target_width);
             if bar.bar_type == CastingBarType::FRONT {
                 //FRONT
@@ -235,14 +379,20 @@ pub fn update_castingbar(
             }
 
             sprite.custom_size = Some(size);
+            sprite.color = if showing_interrupt {
+                Color::RED
+            } else {
+                bar.color
+            };
             t.translation = Vec3::new(
                 ent_t.translation.x - bar.width / 2.,
                 ent_t.translation.y + margin,
-                if bar.bar_type == CastingBarType::FRONT {
-                    2.
-                } else {
-                    1.
-                },
+                RenderLayer::Overhead.z()
+                    + if bar.bar_type == CastingBarType::FRONT {
+                        0.2
+                    } else {
+                        0.1
+                    },
             );
         } else {
             size.x = 0.;
@@ -250,41 +400,3 @@ pub fn update_castingbar(
         }
     }
 }
-
-#[derive(Component)]
-pub struct SpawnTimer(Timer);
-
-#[derive(Bundle)]
-pub struct SpawnTimerBundle {
-    timer: SpawnTimer,
-}
-pub fn spawn_timer(command: &mut Commands, secs: f32) {
-    command.spawn(SpawnTimer(Timer::new(
-        Duration::from_secs_f32(secs),
-        TimerMode::Repeating,
-    )));
-}
-
-pub fn random_spawn_monster(
-    mut command: Commands,
-    time: Res<Time>,
-    mut timer: Query<&mut SpawnTimer>,
-) {
-    let mut timer = timer.single_mut();
-
-    timer.0.tick(time.delta());
-
-    if timer.0.finished() {
-        let mut rng = rand::thread_rng();
-        let size = 500.;
-        let rand_x = rng.gen::<f32>() * size;
-        let rand_y = rng.gen::<f32>() * size;
-        let rand_z = rng.gen::<f32>() * size;
-        spawn_monster(
-            &mut command,
-            String::from("Devil Cruise"),
-            Stat::new(20., 30., 1, 5000., 50.),
-            Transform::from_xyz(400. + rand_x, rand_y, rand_z),
-        );
-    }
-}
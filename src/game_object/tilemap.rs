@@ -1,9 +1,468 @@
-pub struct TilemapBuilder {}
+use std::path::PathBuf;
+
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::{
+        io::Reader, Asset, AssetApp, AssetLoader, AssetServer, Assets, AsyncReadExt, Handle,
+        LoadContext,
+    },
+    ecs::{
+        component::Component,
+        query::{Added, With, Without},
+        schedule::{common_conditions::in_state, IntoSystemConfigs},
+        system::{Commands, Query, Res, ResMut},
+    },
+    log::warn,
+    math::{Rect, Vec2, Vec3},
+    prelude::{default, SpatialBundle},
+    reflect::TypePath,
+    render::{color::Color, texture::Image},
+    sprite::{Sprite, SpriteBundle},
+    transform::components::Transform,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{data::DataLoaderError, AppState};
+
+use super::{
+    building::place_building,
+    chunk::{ChunkedTiles, TileLayer, TileSprite},
+    components::TeamType,
+    encounter::spawn_encounter,
+    environment::place_object,
+    ground_effect::ZoneShape,
+    physics::ObstacleBundle,
+    player::Player,
+    spawner::{spawn_spawner, SpawnEntry, SpawnerDefinition, WaveSchedule},
+};
+
+pub struct TilemapPlugin;
+
+impl Plugin for TilemapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<TiledMap>()
+            .register_asset_loader(TiledLoader)
+            .add_systems(
+                Update,
+                (build_tilemaps, place_players_at_start)
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+/// the top bits of a tile id tell how the tile is flipped.
+const TILE_FLIP_FLAGS: u32 = 0xE000_0000;
+/// room between players placed around the same start point.
+const PLAYER_START_SPACING: f32 = 60.;
+
+/**
+ Map made with the Tiled editor, saved as JSON (`.tmj`).
+
+ only finite orthogonal maps with embedded tilesets are supported.
+ the images of the tilesets are loaded along with the map, relative to the map file.
+*/
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct TiledMap {
+    pub width: u32,
+    pub height: u32,
+    #[serde(rename = "tilewidth")]
+    pub tile_width: f32,
+    #[serde(rename = "tileheight")]
+    pub tile_height: f32,
+    pub layers: Vec<TiledLayer>,
+    #[serde(default)]
+    pub tilesets: Vec<TiledTileset>,
+    /// image of every tileset, in the same order.
+    #[serde(skip)]
+    #[dependency]
+    pub images: Vec<Handle<Image>>,
+}
+
+impl TiledMap {
+    /// world position of a point given in map pixels, the map being centered on the origin.
+    pub fn to_world(&self, x: f32, y: f32) -> Vec2 {
+        let size = self.pixel_size();
+        Vec2::new(x - size.x / 2., size.y / 2. - y)
+    }
+
+    pub fn pixel_size(&self) -> Vec2 {
+        Vec2::new(
+            self.width as f32 * self.tile_width,
+            self.height as f32 * self.tile_height,
+        )
+    }
+
+    /// tileset of a tile id, with the index of the tile in it.
+    fn tile(&self, gid: u32) -> Option<(usize, usize)> {
+        let gid = gid & !TILE_FLIP_FLAGS;
+        if gid == 0 {
+            return None;
+        }
+        self.tilesets
+            .iter()
+            .enumerate()
+            .filter(|(_, tileset)| tileset.first_gid <= gid)
+            .max_by_key(|(_, tileset)| tileset.first_gid)
+            .map(|(i, tileset)| (i, (gid - tileset.first_gid) as usize))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum TiledLayer {
+    #[serde(rename = "tilelayer")]
+    Tiles {
+        width: u32,
+        height: u32,
+        data: Vec<u32>,
+        #[serde(default = "visible_by_default")]
+        visible: bool,
+    },
+    #[serde(rename = "objectgroup")]
+    Objects { objects: Vec<TiledObject> },
+    /// image and group layers aren't used by the game.
+    #[serde(other)]
+    Other,
+}
+
+fn visible_by_default() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TiledTileset {
+    #[serde(rename = "firstgid")]
+    pub first_gid: u32,
+    pub image: String,
+    #[serde(rename = "tilewidth")]
+    pub tile_width: f32,
+    #[serde(rename = "tileheight")]
+    pub tile_height: f32,
+    pub columns: usize,
+    #[serde(default)]
+    pub margin: f32,
+    #[serde(default)]
+    pub spacing: f32,
+}
+
+impl TiledTileset {
+    /// pixels of the tile in the tileset image.
+    fn rect(&self, tile: usize) -> Rect {
+        let column = (tile % self.columns.max(1)) as f32;
+        let row = (tile / self.columns.max(1)) as f32;
+        let min = Vec2::new(
+            self.margin + column * (self.tile_width + self.spacing),
+            self.margin + row * (self.tile_height + self.spacing),
+        );
+        Rect::from_corners(min, min + Vec2::new(self.tile_width, self.tile_height))
+    }
+}
+
+/**
+ Object of an object layer, what it spawns depends on its `type`.
+
+ - `object`: the `object` of the [`super::environment::EnvironmentCatalog`], like a tree.
+ - `spawner`: a [`super::spawner::Spawner`] covering the object, its `monster` at `level`.
+ - `building`: the `building` of the [`super::building::Blueprints`] for the `team` ("player" or
+   "monster", players by default) at `level`, or a solid block covering the object without one.
+ - `player_start`: where the players are placed when the map is built.
+ - `encounter`: the boss of the `encounter` file.
+*/
+#[derive(Deserialize, Debug)]
+pub struct TiledObject {
+    #[serde(default)]
+    pub name: String,
+    /// called "class" since Tiled 1.9.
+    #[serde(rename = "type", alias = "class", default)]
+    pub kind: String,
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub width: f32,
+    #[serde(default)]
+    pub height: f32,
+    #[serde(default)]
+    pub ellipse: bool,
+    #[serde(default)]
+    pub properties: Vec<TiledProperty>,
+}
+
+impl TiledObject {
+    /// world position of the object's center.
+    fn center(&self, map: &TiledMap) -> Vec2 {
+        map.to_world(self.x + self.width / 2., self.y + self.height / 2.)
+    }
+
+    fn property(&self, name: &str) -> Option<&Value> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+            .map(|property| &property.value)
+    }
+
+    fn f32_property(&self, name: &str) -> Option<f32> {
+        self.property(name)
+            .and_then(Value::as_f64)
+            .map(|value| value as f32)
+    }
+
+    fn u32_property(&self, name: &str) -> Option<u32> {
+        self.property(name)
+            .and_then(Value::as_u64)
+            .map(|value| value as u32)
+    }
+
+    fn str_property(&self, name: &str) -> Option<&str> {
+        self.property(name).and_then(Value::as_str)
+    }
+}
+
+/// custom property set on an object in Tiled.
+#[derive(Deserialize, Debug)]
+pub struct TiledProperty {
+    pub name: String,
+    pub value: Value,
+}
+
+pub struct TilemapBuilder;
 
 impl TilemapBuilder {
-    pub fn new() -> Self {
-        Self {}
+    /// parses a map saved by Tiled as JSON, without its tileset images.
+    pub fn from_json(bytes: &[u8]) -> Result<TiledMap, DataLoaderError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+pub struct TiledLoader;
+
+impl AssetLoader for TiledLoader {
+    type Asset = TiledMap;
+    type Settings = ();
+    type Error = DataLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let mut map = TilemapBuilder::from_json(&bytes)?;
+            let folder = load_context
+                .path()
+                .parent()
+                .map(PathBuf::from)
+                .unwrap_or_default();
+            map.images = map
+                .tilesets
+                .iter()
+                .map(|tileset| load_context.load(folder.join(&tileset.image)))
+                .collect();
+            Ok(map)
+        })
     }
 
-    pub fn from_json(&mut self) -> () {}
+    fn extensions(&self) -> &[&str] {
+        &["tmj"]
+    }
+}
+
+/// the entities of a [`TiledMap`], built once the map is loaded.
+#[derive(Component)]
+pub struct Tilemap {
+    map: Handle<TiledMap>,
+    built: bool,
+}
+
+/// where the players are put when the map is built.
+#[derive(Component)]
+pub struct PlayerStart;
+
+/// loads the map at `path` and builds it once loaded.
+pub fn spawn_tilemap(commands: &mut Commands, server: &AssetServer, path: &'static str) {
+    commands.spawn((
+        Tilemap {
+            map: server.load(path),
+            built: false,
+        },
+        SpatialBundle::default(),
+    ));
+}
+
+/// hands the tile layers of every loaded map to the chunks and spawns its objects.
+pub fn build_tilemaps(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    maps: Res<Assets<TiledMap>>,
+    mut chunked_tiles: ResMut<ChunkedTiles>,
+    mut tilemaps: Query<&mut Tilemap>,
+) {
+    for mut tilemap in &mut tilemaps {
+        if tilemap.built {
+            continue;
+        }
+        let Some(map) = maps.get(&tilemap.map) else {
+            continue;
+        };
+        tilemap.built = true;
+
+        for (index, layer) in map.layers.iter().enumerate() {
+            match layer {
+                TiledLayer::Tiles {
+                    width,
+                    height,
+                    data,
+                    visible,
+                } => {
+                    if !visible {
+                        continue;
+                    }
+                    let tiles = data
+                        .iter()
+                        .map(|gid| {
+                            let (tileset, tile) = map.tile(*gid)?;
+                            Some(TileSprite {
+                                image: Some(map.images[tileset].clone()),
+                                rect: Some(map.tilesets[tileset].rect(tile)),
+                                color: Color::WHITE,
+                            })
+                        })
+                        .collect();
+                    chunked_tiles.add_layer(TileLayer {
+                        origin: map.to_world(0., 0.),
+                        tile_size: Vec2::new(map.tile_width, map.tile_height),
+                        width: *width,
+                        height: *height,
+                        tiles,
+                        z: index as f32,
+                    });
+                }
+                TiledLayer::Objects { objects } => {
+                    for object in objects {
+                        spawn_map_object(&mut commands, &server, map, object);
+                    }
+                }
+                TiledLayer::Other => {}
+            }
+        }
+    }
+}
+
+fn spawn_map_object(
+    commands: &mut Commands,
+    server: &AssetServer,
+    map: &TiledMap,
+    object: &TiledObject,
+) {
+    let center = object.center(map);
+    let transform = Transform::from_translation(center.extend(0.));
+    match object.kind.as_str() {
+        "object" => {
+            let Some(id) = object.str_property("object") else {
+                warn!("object {:?} has no catalog id", object.name);
+                return;
+            };
+            // catalog objects stand on the bottom middle of their box.
+            let base = map.to_world(object.x + object.width / 2., object.y + object.height);
+            place_object(commands, id, Transform::from_translation(base.extend(0.)));
+        }
+        "spawner" => {
+            let Some(monster) = object.str_property("monster") else {
+                warn!("spawner {:?} has no monster", object.name);
+                return;
+            };
+            let region = if object.ellipse {
+                ZoneShape::Circle {
+                    radius: object.width.min(object.height) / 2.,
+                }
+            } else {
+                ZoneShape::Rect {
+                    width: object.width,
+                    height: object.height,
+                }
+            };
+            let waves = object
+                .f32_property("wave_interval")
+                .map(|interval| WaveSchedule {
+                    interval,
+                    size: object.u32_property("wave_size").unwrap_or(1),
+                    waves: object.u32_property("waves"),
+                });
+            spawn_spawner(
+                commands,
+                SpawnerDefinition {
+                    name: object.name.clone(),
+                    region,
+                    monsters: vec![SpawnEntry {
+                        monster: monster.to_string(),
+                        level: object.u32_property("level").unwrap_or(1),
+                        weight: 1,
+                    }],
+                    cap: object.u32_property("cap").unwrap_or(1) as usize,
+                    respawn_delay: object.f32_property("respawn_delay").unwrap_or(10.),
+                    waves,
+                    active: object
+                        .property("active")
+                        .and_then(Value::as_bool)
+                        .unwrap_or(true),
+                },
+                transform,
+            );
+        }
+        "building" => {
+            if let Some(id) = object.str_property("building") {
+                let team = match object.str_property("team") {
+                    Some("monster") => TeamType::MONSTER,
+                    _ => TeamType::PLAYER,
+                };
+                let level = object.u32_property("level").unwrap_or(1);
+                place_building(commands, id, team, level, transform);
+                return;
+            }
+            let size = Vec2::new(object.width, object.height);
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::GRAY,
+                        custom_size: Some(size),
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                },
+                ObstacleBundle::new(size, Vec2::ZERO),
+            ));
+        }
+        "player_start" => {
+            commands.spawn((PlayerStart, transform));
+        }
+        "encounter" => match object.str_property("encounter") {
+            Some(path) => spawn_encounter(commands, server, path.to_string(), transform),
+            None => warn!("encounter {:?} has no encounter file", object.name),
+        },
+        kind => warn!("unknown map object type {:?}", kind),
+    }
+}
+
+/// puts the players around the start point of a freshly built map.
+pub fn place_players_at_start(
+    starts: Query<&Transform, (Added<PlayerStart>, Without<Player>)>,
+    mut players: Query<&mut Transform, With<Player>>,
+) {
+    let Some(start) = starts.iter().next() else {
+        return;
+    };
+    for (i, mut t) in players.iter_mut().enumerate() {
+        let offset = Vec3::new(
+            (i % 3) as f32 * PLAYER_START_SPACING,
+            (i / 3) as f32 * PLAYER_START_SPACING,
+            0.,
+        );
+        t.translation = start.translation + offset;
+    }
 }
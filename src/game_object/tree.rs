@@ -1,68 +1,21 @@
 use bevy::{
-    asset::{AssetServer, Assets},
     ecs::{
-        bundle::Bundle,
         component::Component,
-        system::{Commands, Query, Res, ResMut},
+        system::{Query, Res},
     },
-    math::Vec2,
     prelude::{Deref, DerefMut},
-    sprite::{SpriteSheetBundle, TextureAtlas, TextureAtlasSprite},
+    sprite::TextureAtlasSprite,
     time::{Time, Timer},
-    transform::components::Transform,
 };
 
 #[derive(Component)]
 pub struct AnimationIndices {
-    first: usize,
-    last: usize,
+    pub first: usize,
+    pub last: usize,
 }
 
 #[derive(Component, Deref, DerefMut)]
-pub struct AnimationTimer(Timer);
-
-#[derive(Component)]
-pub struct AnimatedObject {
-    name: String,
-    // collidable: bool,
-}
-
-#[derive(Bundle)]
-pub struct AnimatedObjectBundle {
-    object: AnimatedObject,
-    sprite: SpriteSheetBundle,
-    animation_indices: AnimationIndices,
-    timer: AnimationTimer,
-}
-
-pub fn spawn_tree(
-    commands: &mut Commands,
-    server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    tree_type: u32,
-    transform: Transform,
-) {
-    let w = 129.;
-    let h = 137.;
-    let texture = server.load("sprites/trees/anim_tree/spritesheet.png");
-    let texture_atlas = TextureAtlas::from_grid(texture, Vec2::new(w, h), 6, 5, None, None);
-    let texture_atlas_handle = texture_atlases.add(texture_atlas);
-    let animation_indices = AnimationIndices { first: 1, last: 29 };
-
-    commands.spawn(AnimatedObjectBundle {
-        object: AnimatedObject {
-            name: "tree1".to_string(),
-        },
-        sprite: SpriteSheetBundle {
-            texture_atlas: texture_atlas_handle,
-            sprite: TextureAtlasSprite::new(animation_indices.first),
-            transform,
-            ..Default::default()
-        },
-        animation_indices,
-        timer: AnimationTimer(Timer::from_seconds(0.1, bevy::time::TimerMode::Repeating)),
-    });
-}
+pub struct AnimationTimer(pub Timer);
 
 pub fn animate_sprite(
     time: Res<Time>,
@@ -0,0 +1,593 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    asset::{AssetServer, Handle},
+    ecs::system::Commands,
+    hierarchy::BuildChildren,
+    math::{UVec2, Vec2},
+    prelude::SpatialBundle,
+    render::{color::Color, texture::Image},
+    transform::{components::Transform, TransformBundle},
+};
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use super::{
+    chunk::{ChunkedTiles, TileLayer, TileSprite},
+    encounter::spawn_encounter,
+    environment::place_object,
+    ground_effect::ZoneShape,
+    physics::ObstacleBundle,
+    spawner::{spawn_spawner, SpawnEntry, SpawnerDefinition},
+    tilemap::PlayerStart,
+};
+
+/// trees of the environment catalog scattered over the grass.
+pub const TREE_OBJECTS: [&str; 3] = ["tree_01", "tree_09", "tree_14"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terrain {
+    Grass,
+    Dirt,
+    Water,
+    Cliff,
+}
+
+impl Terrain {
+    pub fn walkable(self) -> bool {
+        matches!(self, Terrain::Grass | Terrain::Dirt)
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Terrain::Grass => Color::rgb(0.33, 0.55, 0.25),
+            Terrain::Dirt => Color::WHITE,
+            Terrain::Water => Color::rgb(0.2, 0.4, 0.75),
+            Terrain::Cliff => Color::rgb(0.4, 0.37, 0.35),
+        }
+    }
+}
+
+/**
+ Knobs of the world generator.
+
+ noise values are between -1 and 1: elevation under `water_level` is water, over `cliff_level` a cliff,
+ and the walkable rest turns to dirt where the moisture is over `dirt_level`.
+*/
+#[derive(Debug, Clone)]
+pub struct WorldGenSettings {
+    /// size of the world in tiles.
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: f32,
+    /// how zoomed in the noise is, smaller values giving bigger lakes and mountains.
+    pub frequency: f64,
+    pub water_level: f64,
+    pub cliff_level: f64,
+    pub dirt_level: f64,
+    /// tiles around the start kept as open grass.
+    pub start_clearing: u32,
+    /// width in tiles of the widest unit, the least width of every way through the world.
+    pub clearance: u32,
+    /// chance of a tree on a grass tile deep in a forest.
+    pub tree_density: f64,
+    /// the forest noise under which no tree grows.
+    pub forest_level: f64,
+    /// least tiles between two trees, more than `clearance` so there's always a way between them.
+    pub tree_spacing: u32,
+    pub camp_count: u32,
+    /// radius in tiles of the area the monsters of a camp spawn in.
+    pub camp_radius: u32,
+    /// least tiles between the start and a camp, and between two camps.
+    pub camp_distance: u32,
+    /// tiles further from the start for a camp to gain a level.
+    pub tiles_per_level: u32,
+    pub camp_monsters: Vec<String>,
+    /// encounter file of the boss, waiting on the reachable grass furthest from the start.
+    pub boss_encounter: Option<String>,
+}
+
+impl Default for WorldGenSettings {
+    fn default() -> Self {
+        Self {
+            width: 96,
+            height: 96,
+            tile_size: 32.,
+            frequency: 0.04,
+            water_level: -0.5,
+            cliff_level: 0.6,
+            dirt_level: 0.25,
+            start_clearing: 5,
+            // the biggest monsters are 90 wide on 32 tiles.
+            clearance: 3,
+            tree_density: 0.35,
+            forest_level: 0.1,
+            tree_spacing: 4,
+            camp_count: 6,
+            camp_radius: 3,
+            camp_distance: 18,
+            tiles_per_level: 15,
+            camp_monsters: vec![String::from("devil_cruise"), String::from("imp")],
+            boss_encounter: Some(String::from("encounters/devil_king.encounter.ron")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TreePlacement {
+    pub tile: UVec2,
+    pub object: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CampPlacement {
+    pub tile: UVec2,
+    /// radius in tiles of the spawn area, every tile of it reachable.
+    pub radius: u32,
+    pub monster: String,
+    pub level: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BossPlacement {
+    pub tile: UVec2,
+    pub encounter: String,
+}
+
+/**
+ World built from a seed by [`generate_world`], the same seed always giving the same world.
+
+ every walkable tile can be reached on foot from `start` by a unit `clearance` tiles wide.
+*/
+#[derive(Debug, Clone)]
+pub struct GeneratedWorld {
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: f32,
+    pub clearance: u32,
+    /// terrain of every tile, row by row from the top.
+    pub tiles: Vec<Terrain>,
+    pub start: UVec2,
+    pub trees: Vec<TreePlacement>,
+    pub camps: Vec<CampPlacement>,
+    pub boss: Option<BossPlacement>,
+}
+
+impl GeneratedWorld {
+    pub fn terrain(&self, tile: UVec2) -> Terrain {
+        self.tiles[self.index(tile)]
+    }
+
+    /// world position of the center of a tile, the world being centered on the origin.
+    pub fn to_world(&self, tile: UVec2) -> Vec2 {
+        Vec2::new(
+            (tile.x as f32 + 0.5 - self.width as f32 / 2.) * self.tile_size,
+            (self.height as f32 / 2. - tile.y as f32 - 0.5) * self.tile_size,
+        )
+    }
+
+    fn index(&self, tile: UVec2) -> usize {
+        (tile.y * self.width + tile.x) as usize
+    }
+
+    fn neighbours(&self, tile: UVec2) -> impl Iterator<Item = UVec2> + '_ {
+        [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .into_iter()
+            .filter_map(move |(dx, dy)| {
+                let x = tile.x.checked_add_signed(dx)?;
+                let y = tile.y.checked_add_signed(dy)?;
+                (x < self.width && y < self.height).then_some(UVec2::new(x, y))
+            })
+    }
+
+    /// the tiles of the `clearance` wide square centered on `tile`, inside the world.
+    fn footprint(&self, tile: UVec2) -> impl Iterator<Item = UVec2> + '_ {
+        let low = (self.clearance.max(1) as i32 - 1) / 2;
+        let high = self.clearance.max(1) as i32 / 2;
+        (-low..=high)
+            .flat_map(move |dy| (-low..=high).map(move |dx| (dx, dy)))
+            .filter_map(move |(dx, dy)| {
+                let x = tile.x.checked_add_signed(dx)?;
+                let y = tile.y.checked_add_signed(dy)?;
+                (x < self.width && y < self.height).then_some(UVec2::new(x, y))
+            })
+    }
+
+    /// whether the widest unit can stand centered on the tile, the outside of the world being open.
+    fn fits(&self, tile: UVec2) -> bool {
+        self.footprint(tile)
+            .all(|tile| self.terrain(tile).walkable())
+    }
+
+    /// every tile the widest unit can stand on after walking from the start.
+    fn reachable(&self) -> Vec<bool> {
+        let mut reached = vec![false; self.tiles.len()];
+        let mut queue = VecDeque::from([self.start]);
+        reached[self.index(self.start)] = true;
+        while let Some(tile) = queue.pop_front() {
+            for next in self.neighbours(tile) {
+                let i = self.index(next);
+                if !reached[i] && self.fits(next) {
+                    reached[i] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        reached
+    }
+
+    /**
+     the tiles a point within `radius` tiles of the center of `tile` can fall on.
+
+     `None` when some of them are outside of the world.
+    */
+    fn region(&self, tile: UVec2, radius: u32) -> Option<Vec<UVec2>> {
+        let reach = radius as i32 + 1;
+        let mut tiles = Vec::new();
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                if dx * dx + dy * dy > reach * reach {
+                    continue;
+                }
+                let x = tile.x.checked_add_signed(dx)?;
+                let y = tile.y.checked_add_signed(dy)?;
+                if x >= self.width || y >= self.height {
+                    return None;
+                }
+                tiles.push(UVec2::new(x, y));
+            }
+        }
+        Some(tiles)
+    }
+
+    /// every tile under the widest unit standing on a reached tile.
+    fn covered(&self, reached: &[bool]) -> Vec<bool> {
+        let mut covered = vec![false; self.tiles.len()];
+        for i in (0..self.tiles.len()).filter(|i| reached[*i]) {
+            let tile = UVec2::new(i as u32 % self.width, i as u32 / self.width);
+            for under in self.footprint(tile) {
+                covered[self.index(under)] = true;
+            }
+        }
+        covered
+    }
+
+    /// digs a `clearance` wide dirt path from `from` to the closest reached tile, through water and cliffs.
+    fn carve_path(&mut self, from: UVec2, reached: &[bool]) {
+        let mut previous: Vec<Option<UVec2>> = vec![None; self.tiles.len()];
+        let mut seen = vec![false; self.tiles.len()];
+        let mut queue = VecDeque::from([from]);
+        seen[self.index(from)] = true;
+        while let Some(tile) = queue.pop_front() {
+            if reached[self.index(tile)] {
+                let mut step = Some(tile);
+                while let Some(tile) = step {
+                    let footprint: Vec<UVec2> = self.footprint(tile).collect();
+                    for under in footprint {
+                        let i = self.index(under);
+                        if !self.tiles[i].walkable() {
+                            self.tiles[i] = Terrain::Dirt;
+                        }
+                    }
+                    step = previous[self.index(tile)];
+                }
+                return;
+            }
+            let neighbours: Vec<UVec2> = self.neighbours(tile).collect();
+            for next in neighbours {
+                let i = self.index(next);
+                if !seen[i] {
+                    seen[i] = true;
+                    previous[i] = Some(tile);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    /// tile distance ignoring diagonals' extra length, what spacing rules use.
+    fn distance(a: UVec2, b: UVec2) -> u32 {
+        a.x.abs_diff(b.x).max(a.y.abs_diff(b.y))
+    }
+}
+
+fn noise(rng: &mut ChaCha8Rng, frequency: f64) -> Fbm<Perlin> {
+    Fbm::<Perlin>::new(rng.gen())
+        .set_octaves(4)
+        .set_frequency(frequency)
+}
+
+/// builds the terrain, trees and camps of the world of `seed`.
+pub fn generate_world(seed: u64, settings: &WorldGenSettings) -> GeneratedWorld {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let elevation = noise(&mut rng, settings.frequency);
+    let moisture = noise(&mut rng, settings.frequency * 2.);
+    let forest = noise(&mut rng, settings.frequency * 1.5);
+
+    let start = UVec2::new(settings.width / 2, settings.height / 2);
+    let mut world = GeneratedWorld {
+        width: settings.width,
+        height: settings.height,
+        tile_size: settings.tile_size,
+        clearance: settings.clearance,
+        tiles: Vec::with_capacity((settings.width * settings.height) as usize),
+        start,
+        trees: Vec::new(),
+        camps: Vec::new(),
+        boss: None,
+    };
+
+    for y in 0..settings.height {
+        for x in 0..settings.width {
+            let point = [x as f64, y as f64];
+            let tile = UVec2::new(x, y);
+            let height = elevation.get(point);
+            let terrain = if GeneratedWorld::distance(tile, start) <= settings.start_clearing {
+                Terrain::Grass
+            } else if height < settings.water_level {
+                Terrain::Water
+            } else if height > settings.cliff_level {
+                Terrain::Cliff
+            } else if moisture.get(point) > settings.dirt_level {
+                Terrain::Dirt
+            } else {
+                Terrain::Grass
+            };
+            world.tiles.push(terrain);
+        }
+    }
+
+    // link every cut off area to the start, until one flood fill reaches them all.
+    loop {
+        let reached = world.reachable();
+        let covered = world.covered(&reached);
+        let lost = (0..world.tiles.len())
+            .find(|i| world.tiles[*i].walkable() && !covered[*i])
+            .map(|i| UVec2::new(i as u32 % world.width, i as u32 / world.width));
+        let Some(lost) = lost else {
+            break;
+        };
+        world.carve_path(lost, &reached);
+    }
+
+    // trees grow in the middle of grass fields, leaving room for the widest unit around them.
+    for y in 0..settings.height {
+        for x in 0..settings.width {
+            let tile = UVec2::new(x, y);
+            if GeneratedWorld::distance(tile, start) <= settings.start_clearing {
+                continue;
+            }
+            let open = (tile.y.saturating_sub(settings.clearance)
+                ..=(tile.y + settings.clearance).min(settings.height - 1))
+                .flat_map(|y| {
+                    (tile.x.saturating_sub(settings.clearance)
+                        ..=(tile.x + settings.clearance).min(settings.width - 1))
+                        .map(move |x| UVec2::new(x, y))
+                })
+                .all(|next| world.terrain(next).walkable());
+            let open = open && world.terrain(tile) == Terrain::Grass;
+            if !open {
+                continue;
+            }
+            let woods = forest.get([x as f64, y as f64]);
+            if woods < settings.forest_level {
+                continue;
+            }
+            // rolled for every open tile so the stream of numbers only depends on the terrain.
+            let roll: f64 = rng.gen();
+            let object = TREE_OBJECTS[rng.gen_range(0..TREE_OBJECTS.len())];
+            let crowded = world
+                .trees
+                .iter()
+                .any(|tree| GeneratedWorld::distance(tree.tile, tile) < settings.tree_spacing);
+            if roll < settings.tree_density * woods && !crowded {
+                world.trees.push(TreePlacement { tile, object });
+            }
+        }
+    }
+
+    // camps sit on reachable grass, far enough from the start and each other,
+    // with room for the widest unit anywhere in their spawn area.
+    let reached = world.reachable();
+    let mut candidates: Vec<UVec2> = (0..world.tiles.len())
+        .filter(|i| reached[*i] && world.tiles[*i] == Terrain::Grass)
+        .map(|i| UVec2::new(i as u32 % world.width, i as u32 / world.width))
+        .filter(|tile| GeneratedWorld::distance(*tile, start) >= settings.camp_distance)
+        .filter(|tile| {
+            world
+                .region(*tile, settings.camp_radius)
+                .is_some_and(|region| {
+                    region.iter().all(|under| {
+                        reached[world.index(*under)]
+                            && world.trees.iter().all(|tree| tree.tile != *under)
+                    })
+                })
+        })
+        .collect();
+    while world.camps.len() < settings.camp_count as usize && !candidates.is_empty() {
+        let tile = candidates.swap_remove(rng.gen_range(0..candidates.len()));
+        let crowded = world
+            .camps
+            .iter()
+            .any(|camp| GeneratedWorld::distance(camp.tile, tile) < settings.camp_distance);
+        if crowded || settings.camp_monsters.is_empty() {
+            continue;
+        }
+        let monster =
+            settings.camp_monsters[rng.gen_range(0..settings.camp_monsters.len())].clone();
+        let level = 1 + GeneratedWorld::distance(tile, start) / settings.tiles_per_level.max(1);
+        world.camps.push(CampPlacement {
+            tile,
+            radius: settings.camp_radius,
+            monster,
+            level,
+        });
+    }
+
+    // the boss waits as far as it can get from the start.
+    world.boss = settings.boss_encounter.as_ref().and_then(|encounter| {
+        (0..world.tiles.len())
+            .filter(|i| reached[*i] && world.tiles[*i] == Terrain::Grass)
+            .map(|i| UVec2::new(i as u32 % world.width, i as u32 / world.width))
+            .filter(|tile| world.trees.iter().all(|tree| tree.tile != *tile))
+            .filter(|tile| world.camps.iter().all(|camp| camp.tile != *tile))
+            // kept off the edge, so nothing pulls the boss out of the world.
+            .filter(|tile| {
+                tile.x >= world.clearance
+                    && tile.y >= world.clearance
+                    && tile.x + world.clearance < world.width
+                    && tile.y + world.clearance < world.height
+            })
+            .max_by_key(|tile| GeneratedWorld::distance(*tile, start))
+            .map(|tile| BossPlacement {
+                tile,
+                encounter: encounter.clone(),
+            })
+    });
+
+    world
+}
+
+/// hands the tiles to the chunks and spawns the blocking water and cliffs, the trees, the camps and the boss of a generated world.
+pub fn spawn_world(
+    commands: &mut Commands,
+    server: &AssetServer,
+    tiles: &mut ChunkedTiles,
+    world: &GeneratedWorld,
+) {
+    let dirt: Handle<Image> = server.load("tilesets/dirt.png");
+    tiles.add_layer(TileLayer {
+        origin: world.to_world(UVec2::ZERO) + Vec2::new(-0.5, 0.5) * world.tile_size,
+        tile_size: Vec2::splat(world.tile_size),
+        width: world.width,
+        height: world.height,
+        tiles: world
+            .tiles
+            .iter()
+            .map(|terrain| {
+                Some(match terrain {
+                    Terrain::Dirt => TileSprite {
+                        image: Some(dirt.clone()),
+                        rect: None,
+                        color: terrain.color(),
+                    },
+                    _ => TileSprite::color(terrain.color()),
+                })
+            })
+            .collect(),
+        z: 0.,
+    });
+
+    commands
+        .spawn(SpatialBundle::default())
+        .with_children(|parent| {
+            // one collider per run of blocking tiles in a row rather than one per tile.
+            for y in 0..world.height {
+                let mut x = 0;
+                while x < world.width {
+                    if world.terrain(UVec2::new(x, y)).walkable() {
+                        x += 1;
+                        continue;
+                    }
+                    let first = x;
+                    while x < world.width && !world.terrain(UVec2::new(x, y)).walkable() {
+                        x += 1;
+                    }
+                    let left = world.to_world(UVec2::new(first, y));
+                    let right = world.to_world(UVec2::new(x - 1, y));
+                    parent.spawn((
+                        ObstacleBundle::new(
+                            Vec2::new((x - first) as f32 * world.tile_size, world.tile_size),
+                            Vec2::ZERO,
+                        ),
+                        TransformBundle::from_transform(Transform::from_translation(
+                            ((left + right) / 2.).extend(0.),
+                        )),
+                    ));
+                }
+            }
+        });
+
+    for tree in &world.trees {
+        // trees stand with their trunk on the tile.
+        let position = world.to_world(tree.tile) - Vec2::new(0., world.tile_size / 2.);
+        place_object(
+            commands,
+            tree.object,
+            Transform::from_translation(position.extend(0.)),
+        );
+    }
+
+    for camp in &world.camps {
+        spawn_spawner(
+            commands,
+            SpawnerDefinition {
+                name: format!("camp_{}_{}", camp.tile.x, camp.tile.y),
+                region: ZoneShape::Circle {
+                    radius: world.tile_size * camp.radius as f32,
+                },
+                monsters: vec![SpawnEntry {
+                    monster: camp.monster.clone(),
+                    level: camp.level,
+                    weight: 1,
+                }],
+                cap: 4,
+                respawn_delay: 20.,
+                waves: None,
+                active: true,
+            },
+            Transform::from_translation(world.to_world(camp.tile).extend(0.)),
+        );
+    }
+
+    if let Some(boss) = &world.boss {
+        spawn_encounter(
+            commands,
+            server,
+            boss.encounter.clone(),
+            Transform::from_translation(world.to_world(boss.tile).extend(0.)),
+        );
+    }
+
+    commands.spawn((
+        PlayerStart,
+        Transform::from_translation(world.to_world(world.start).extend(0.)),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_world() {
+        let settings = WorldGenSettings::default();
+        for seed in [0, 7, 12345] {
+            let a = generate_world(seed, &settings);
+            let b = generate_world(seed, &settings);
+            assert_eq!(a.tiles, b.tiles);
+            assert_eq!(a.start, b.start);
+            assert_eq!(a.trees, b.trees);
+            assert_eq!(a.camps, b.camps);
+            assert_eq!(a.boss, b.boss);
+        }
+    }
+
+    #[test]
+    fn every_walkable_tile_is_reachable() {
+        let settings = WorldGenSettings::default();
+        for seed in [0, 7, 12345] {
+            let world = generate_world(seed, &settings);
+            let reached = world.reachable();
+            let covered = world.covered(&reached);
+            for (i, terrain) in world.tiles.iter().enumerate() {
+                assert!(!terrain.walkable() || covered[i], "seed {seed}, tile {i}");
+            }
+            for camp in &world.camps {
+                let region = world.region(camp.tile, camp.radius).unwrap();
+                assert!(region.iter().all(|tile| reached[world.index(*tile)]));
+            }
+        }
+    }
+}
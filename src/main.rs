@@ -5,25 +5,42 @@ use bevy::{input::keyboard::KeyboardInput, prelude::*, winit::WinitPlugin};
 use bevy_pkv::PkvStore;
 use bevy_web_asset::WebAssetPlugin;
 use game_object::{
+    animation::SpriteAnimationPlugin,
     battle::{BattlePlugin, Stat},
+    buff::BuffPlugin,
+    build_mode::BuildModePlugin,
+    building::BuildingPlugin,
     camera::{move_camera, select_gameobject, zoom_camera, CamPlugin},
+    chunk::{ChunkPlugin, ChunkedTiles},
     command::CommandPlugin,
     components::{damage_popup_system, spawn_damage_popup},
-    monster::{spawn_monster, trig_monster_action, MonsterPlugin},
+    encounter::EncounterPlugin,
+    environment::EnvironmentPlugin,
+    frame_atlas::FrameAtlasPlugin,
+    ground_effect::GroundEffectPlugin,
+    monster::{trig_monster_action, MonsterPlugin},
+    pet::PetPlugin,
+    physics::PhysicsPlugin,
     player::{spawn_player, trig_player_action, Class, PlayerPlugin},
-    projectile::{check_collisions, clear_projectile, move_projectile, ProjectilePlugin},
-    skill::{base_attack, use_skill, SkillPlugin},
-    system::{draw_healthbar, random_spawn_monster, spawn_timer, update_castingbar},
-    tree::{animate_sprite, spawn_tree},
+    projectile::{clear_projectile, move_projectile, ProjectilePlugin},
+    render_layer::RenderLayerPlugin,
+    skill::SkillPlugin,
+    spawner::SpawnerPlugin,
+    system::{draw_healthbar, draw_powerbar, update_castingbar},
+    tilemap::{spawn_tilemap, TilemapPlugin},
+    tree::animate_sprite,
+    worldgen::{generate_world, spawn_world, WorldGenSettings},
     GameObjectPlugin, Target,
 };
 mod save;
+use save::save::world_seed;
 mod ui;
-use resources::resource::SelectedList;
+use resources::resource::{SelectedList, Stockpile};
 use ui::{
     ingame::selected_ui_list_system, main_menu::create_world_button, ui_navigation, CurrentPage,
 };
 use web_sys::{js_sys, wasm_bindgen};
+mod data;
 mod event;
 mod game_object;
 
@@ -46,14 +63,30 @@ fn main() {
         .add_plugins((
             WebAssetPlugin::default(),
             DefaultPlugins,
+            PhysicsPlugin,
             SkillPlugin,
             CamPlugin,
             BattlePlugin,
+            BuffPlugin,
             PlayerPlugin,
             ProjectilePlugin,
             MonsterPlugin,
+            PetPlugin,
+            GroundEffectPlugin,
             GameObjectPlugin,
             CommandPlugin,
+            (
+                EncounterPlugin,
+                SpawnerPlugin,
+                TilemapPlugin,
+                ChunkPlugin,
+                EnvironmentPlugin,
+                RenderLayerPlugin,
+                SpriteAnimationPlugin,
+                FrameAtlasPlugin,
+                BuildingPlugin,
+                BuildModePlugin,
+            ),
         ))
         .add_state::<AppState>()
         .insert_resource(CurrentPage::MENU)
@@ -61,6 +94,7 @@ fn main() {
         .insert_resource(PkvStore::new("nasdac", "raidory"))
         .add_event::<KeyboardInput>()
         .insert_resource(SelectedList::new())
+        .init_resource::<Stockpile>()
         .add_systems(
             Startup,
             (setup_main_menu, ui::main_menu::setup_ui).run_if(in_state(AppState::MainMenu)),
@@ -81,8 +115,8 @@ fn main() {
                 damage_popup_system,
                 selected_ui_list_system,
                 draw_healthbar,
+                draw_powerbar,
                 update_castingbar,
-                random_spawn_monster,
                 animate_sprite,
             )
                 .run_if(in_state(AppState::InGame)),
@@ -107,71 +141,76 @@ fn setup_ingame(
     mut _materials: ResMut<Assets<ColorMaterial>>,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut chunked_tiles: ResMut<ChunkedTiles>,
+    mut stockpile: ResMut<Stockpile>,
+    pkv: Res<PkvStore>,
 ) {
     // // Camera
     // commands.spawn((Camera2dBundle::default(), Target(None)));
 
+    // Spawn Map
+    let start = match world_seed(&pkv) {
+        Some(seed) => {
+            let world = generate_world(seed, &WorldGenSettings::default());
+            spawn_world(&mut commands, &asset_server, &mut chunked_tiles, &world);
+            world.to_world(world.start).extend(0.)
+        }
+        None => {
+            spawn_tilemap(&mut commands, &asset_server, "maps/field.tmj");
+            Vec3::ZERO
+        }
+    };
+
+    // enough to build a first tower before any loot
+    stockpile.add("Copper Coin", 40);
+
     // Spawn Player
     let _player_id = spawn_player(
         &mut commands,
+        &asset_server,
         String::from("Jason"),
         Stat::new(200., 500., 1, 3000., 200.),
         Class::MAGE,
-        Transform::from_translation(Vec3::new(-50., 100., 0.)),
+        Transform::from_translation(start + Vec3::new(-50., 100., 0.)),
     );
 
     // Spawn Player
     let _player_id = spawn_player(
         &mut commands,
+        &asset_server,
         String::from("James"),
         Stat::new(200., 500., 1, 3000., 200.),
         Class::MAGE,
-        Transform::from_translation(Vec3::new(50., 70., 0.)),
+        Transform::from_translation(start + Vec3::new(50., 70., 0.)),
     );
 
     // Spawn Player
     let _player_id = spawn_player(
         &mut commands,
+        &asset_server,
         String::from("Kate"),
         Stat::new(200., 500., 1, 3000., 200.),
         Class::MAGE,
-        Transform::from_translation(Vec3::new(-50., 20., 0.)),
+        Transform::from_translation(start + Vec3::new(-50., 20., 0.)),
     );
 
     // Spawn Player
     let _player_id = spawn_player(
         &mut commands,
+        &asset_server,
         String::from("Kim"),
         Stat::new(200., 500., 1, 3000., 200.),
         Class::MAGE,
-        Transform::from_translation(Vec3::new(-20., 200., 0.)),
+        Transform::from_translation(start + Vec3::new(-20., 200., 0.)),
     );
 
     // Spawn Player
     let _player_id = spawn_player(
         &mut commands,
+        &asset_server,
         String::from("Scalar"),
         Stat::new(200., 500., 1, 3000., 200.),
         Class::MAGE,
-        Transform::from_translation(Vec3::new(20., 200., 0.)),
+        Transform::from_translation(start + Vec3::new(20., 200., 0.)),
     );
-
-    // Spawn Monster
-    let _monster_id = spawn_monster(
-        &mut commands,
-        String::from("Devil Cruise"),
-        Stat::new(20., 500., 1, 3000., 200.),
-        Transform::from_xyz(0., 0., 0.),
-    );
-
-    // Spawn Timer
-    spawn_timer(&mut commands, 5.0);
-
-    // spawn_tree(
-    //     &mut commands,
-    //     asset_server,
-    //     texture_atlases,
-    //     0,
-    //     Transform::default(),
-    // );
 }
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use bevy::ecs::{entity::Entity, system::Resource};
 
 #[derive(Resource)]
@@ -12,3 +14,33 @@ impl SelectedList {
         }
     }
 }
+
+/// items the players carry by name, picked up from loot and spent on buildings.
+#[derive(Resource, Default, Debug)]
+pub struct Stockpile {
+    pub items: HashMap<String, u32>,
+}
+
+impl Stockpile {
+    pub fn add(&mut self, item: &str, count: u32) {
+        *self.items.entry(item.to_owned()).or_default() += count;
+    }
+
+    pub fn can_pay(&self, cost: &HashMap<String, u32>) -> bool {
+        cost.iter()
+            .all(|(item, count)| self.items.get(item).copied().unwrap_or(0) >= *count)
+    }
+
+    /// takes every item of `cost` out of the stockpile, or nothing when some are missing.
+    pub fn pay(&mut self, cost: &HashMap<String, u32>) -> bool {
+        if !self.can_pay(cost) {
+            return false;
+        }
+        for (item, count) in cost {
+            if let Some(stock) = self.items.get_mut(item) {
+                *stock -= count;
+            }
+        }
+        true
+    }
+}
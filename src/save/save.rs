@@ -1,6 +1,9 @@
 use bevy::{ecs::system::ResMut, log::info};
 use bevy_pkv::PkvStore;
 
+/// a world is saved as the seed it's generated from.
+const WORLD_SEED: &str = "world_seed";
+
 pub fn create_world(pkv: &mut ResMut<PkvStore>) {
     if let Ok(username) = pkv.get::<String>("username") {
         info!("Welcome back {username}");
@@ -8,4 +11,13 @@ pub fn create_world(pkv: &mut ResMut<PkvStore>) {
         pkv.set_string("username", "alice")
             .expect("failed to store username");
     }
+    let seed: u64 = rand::random();
+    pkv.set(WORLD_SEED, &seed)
+        .expect("failed to store world seed");
+    info!("Created world {seed}");
+}
+
+/// seed of the created world, `None` before a world is created.
+pub fn world_seed(pkv: &PkvStore) -> Option<u64> {
+    pkv.get::<u64>(WORLD_SEED).ok()
 }
@@ -5,7 +5,12 @@ pub enum BattleState {
     IDLE,
     CASTING,
     MOVE,
+    /// backing off from a target standing inside the minimum range of the skill.
     RUNAWAY,
+    /// going back to the spawn point, see [`crate::game_object::monster::Leash`].
+    EVADING,
+    /// running away at low health, see [`crate::game_object::monster::Flee`].
+    FLEEING,
 }
 
 #[derive(Component, PartialEq, Debug)]